@@ -0,0 +1,116 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Return the IP address from an address as it may be copied from a log or a URL
+///
+/// Accepted forms, other than what `IpAddr::from_str` parses:
+/// - bracketed IPv6, e.g. "[2001:db8::1]"
+/// - IPv6 with a zone ID, e.g. "fe80::1%eth0" (the zone ID is dropped)
+/// - decimal or hexadecimal integer IPv4, e.g. "134744072" or "0x08080808"
+/// - an address and port, e.g. "8.8.8.8:53" or "[2001:db8::1]:443"
+///
+/// `None` is returned for anything else.
+pub fn parse_address(input: &str) -> Option<IpAddr> {
+    let input = input.trim();
+    if let Ok(addr) = input.parse::<IpAddr>() {
+        return Some(addr);
+    }
+    if let Ok(socket) = input.parse::<SocketAddr>() {
+        return Some(socket.ip());
+    }
+
+    // Bracketed IPv6, with or without a port
+    if let Some(rest) = input.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((addr, port)) if port.is_empty() || is_port(port) => parse_ipv6(addr),
+            _ => None,
+        };
+    }
+
+    // IPv6 with a zone ID
+    if input.contains('%') {
+        return parse_ipv6(input);
+    }
+
+    // Integer IPv4, with or without a port
+    let integer = match input.split_once(':') {
+        Some((integer, port)) if port.parse::<u16>().is_ok() => integer,
+        Some(_) => return None,
+        None => input,
+    };
+    parse_integer_ipv4(integer).map(IpAddr::V4)
+}
+
+/// Return true for a ":<port>" suffix
+fn is_port(suffix: &str) -> bool {
+    suffix
+        .strip_prefix(':')
+        .is_some_and(|port| port.parse::<u16>().is_ok())
+}
+
+/// Return the IPv6 address, dropping a zone ID (possibly percent-encoded as "%25")
+fn parse_ipv6(input: &str) -> Option<IpAddr> {
+    let addr = match input.split_once('%') {
+        Some((_, "")) => return None,
+        Some((addr, _)) => addr,
+        None => input,
+    };
+    addr.parse::<Ipv6Addr>().ok().map(IpAddr::V6)
+}
+
+/// Return the IPv4 address of a decimal or "0x" prefixed hexadecimal integer
+fn parse_integer_ipv4(input: &str) -> Option<Ipv4Addr> {
+    let integer = match input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    {
+        Some(hex) if hex.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+            u32::from_str_radix(hex, 16).ok()?
+        }
+        Some(_) => return None,
+        None if input.bytes().all(|byte| byte.is_ascii_digit()) => input.parse::<u32>().ok()?,
+        None => return None,
+    };
+    Some(Ipv4Addr::from(integer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        let parse = |input: &str| parse_address(input).map(|addr| addr.to_string());
+        let google = Some(String::from("8.8.8.8"));
+        assert_eq!(parse("8.8.8.8"), google);
+        assert_eq!(parse("8.8.8.8:53"), google);
+        assert_eq!(parse("134744072"), google);
+        assert_eq!(parse("0x08080808"), google);
+        assert_eq!(parse("0x08080808:53"), google);
+        assert_eq!(parse("2001:DB8:0::1"), Some(String::from("2001:db8::1")));
+        assert_eq!(parse("[2001:db8::1]"), Some(String::from("2001:db8::1")));
+        assert_eq!(
+            parse("[2001:db8::1]:443"),
+            Some(String::from("2001:db8::1"))
+        );
+        assert_eq!(parse("fe80::1%eth0"), Some(String::from("fe80::1")));
+        assert_eq!(parse("[fe80::1%25eth0]:443"), Some(String::from("fe80::1")));
+    }
+
+    #[test]
+    fn test_parse_address_invalid() {
+        for input in [
+            "not-an-address",
+            "",
+            "8.8.8.8:port",
+            "[8.8.8.8]",
+            "[2001:db8::1",
+            "fe80::1%",
+            "8.8.8.8%eth0",
+            "4294967296",
+            "0x",
+            "-1",
+        ] {
+            assert_eq!(parse_address(input), None, "{input:?}");
+        }
+    }
+}
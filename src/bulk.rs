@@ -0,0 +1,227 @@
+use std::net::IpAddr;
+
+// https://docs.rs/serde/latest/serde/
+use serde::Deserialize;
+// https://docs.rs/serde_json/latest/serde_json/
+use serde_json::{json, Value};
+
+// IP information lookup
+use actix_geo_widget::{parse_address, GeoError, GeoProvider};
+
+use crate::fields::FieldSelection;
+use crate::problem::ApiError;
+
+/// Maximum length in bytes of a line of a streamed bulk lookup, longer lines are an error
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Return the lookup result (or error) of each input in JSON, in order
+///
+/// The valid addresses are looked up in one batch, an input which could not be
+/// looked up is returned as `{"address": <input>, "error": <problem details>}`
+/// (see `error_item`). Results are limited to the `fields` selected.
+pub fn lookup_items(
+    provider: &dyn GeoProvider,
+    inputs: Vec<(Option<String>, Result<IpAddr, ApiError>)>,
+    locales: &[String],
+    fields: Option<&FieldSelection>,
+) -> Vec<Value> {
+    let valid: Vec<IpAddr> = inputs
+        .iter()
+        .filter_map(|(_, address)| address.as_ref().ok().copied())
+        .collect();
+    let mut results = provider.lookup_batch(&valid, locales).into_iter();
+
+    inputs
+        .into_iter()
+        .map(|(input, address)| {
+            let result = address.and_then(|address| {
+                results
+                    .next()
+                    .unwrap_or(Err(GeoError::AddressNotFound(address)))
+                    .map_err(ApiError::from)
+            });
+            match (result, fields) {
+                (Ok(result), Some(fields)) => fields.apply(&json!(result)),
+                (Ok(result), None) => json!(result),
+                (Err(err), _) => error_item(input, &err),
+            }
+        })
+        .collect()
+}
+
+/// Return the JSON item of an input which could not be looked up
+///
+/// `{"address": <input>, "error": <problem details>}`, the address is left out
+/// when the input could not be read, e.g. `{"line": 3, "error": ...}` for a
+/// line of a streamed bulk lookup which is too long.
+pub fn error_item(input: Option<String>, err: &ApiError) -> Value {
+    let mut item = json!({"error": err.problem()});
+    if let Some(input) = input {
+        item["address"] = json!(input);
+    }
+    if let ApiError::LineTooLong { line, .. } = err {
+        item["line"] = json!(line);
+    }
+    item
+}
+
+/// Line of a streamed bulk lookup given as a JSON object
+#[derive(Debug, Deserialize)]
+struct BulkObject {
+    ip: String,
+}
+
+/// Return the address of a line of a streamed bulk lookup
+///
+/// A line is either an address, e.g. `8.8.8.8`, or a JSON object with an
+/// `ip` field, e.g. `{"ip": "8.8.8.8"}`.
+pub fn parse_line(line: &str) -> Result<IpAddr, ApiError> {
+    let input = if line.starts_with('{') {
+        let object: BulkObject =
+            serde_json::from_str(line).map_err(|err| ApiError::InvalidBody(err.to_string()))?;
+        object.ip
+    } else {
+        line.to_string()
+    };
+    parse_address(&input).ok_or(ApiError::InvalidAddress(input))
+}
+
+/// LineSplitter structure
+///
+/// Splits the chunks of a streamed request body into lines, keeping the
+/// incomplete last line until the next chunk.
+#[derive(Debug, Default)]
+pub struct LineSplitter {
+    buffer: Vec<u8>,
+    // True while the rest of a line longer than MAX_LINE_LENGTH is dropped
+    discarding: bool,
+    // Number of the lines ended so far, including empty lines
+    lines: usize,
+}
+
+impl LineSplitter {
+    /// Return the complete (non-empty) lines after adding a chunk
+    ///
+    /// A line longer than `MAX_LINE_LENGTH` is returned as `ApiError::LineTooLong`
+    /// (once, with its line number) and the rest of it is dropped.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Result<String, ApiError>> {
+        let mut lines = Vec::new();
+        for byte in chunk {
+            if *byte == b'\n' {
+                if !self.discarding {
+                    lines.extend(self.take_line().map(Ok));
+                }
+                self.discarding = false;
+                self.lines += 1;
+            } else if self.discarding {
+                continue;
+            } else if self.buffer.len() == MAX_LINE_LENGTH {
+                self.buffer.clear();
+                self.discarding = true;
+                lines.push(Err(ApiError::LineTooLong {
+                    line: self.lines + 1,
+                    max: MAX_LINE_LENGTH,
+                }));
+            } else {
+                self.buffer.push(*byte);
+            }
+        }
+        lines
+    }
+
+    /// Return the last line when the request body ends without a newline
+    pub fn finish(&mut self) -> Option<Result<String, ApiError>> {
+        match self.discarding {
+            true => None,
+            false => self.take_line().map(Ok),
+        }
+    }
+
+    fn take_line(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.buffer).trim().to_string();
+        self.buffer.clear();
+        match line.is_empty() {
+            true => None,
+            false => Some(line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Return the lines, or the error codes, of a LineSplitter result
+    fn lines(result: Vec<Result<String, ApiError>>) -> Vec<String> {
+        result
+            .into_iter()
+            .map(|line| line.unwrap_or_else(|err| err.code().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_line_splitter() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(lines(splitter.push(b"8.8.8.8\n1.1.")), vec!["8.8.8.8"]);
+        assert_eq!(
+            lines(splitter.push(b"1.1\r\n\n{\"ip\": \"4.3.2.1\"}")),
+            vec!["1.1.1.1"]
+        );
+        assert_eq!(
+            lines(splitter.finish().into_iter().collect()),
+            vec!["{\"ip\": \"4.3.2.1\"}"]
+        );
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn test_line_splitter_long_line() {
+        let mut splitter = LineSplitter::default();
+        let long = vec![b'1'; MAX_LINE_LENGTH * 2];
+        // A long line is an error rather than a truncated line
+        assert_eq!(lines(splitter.push(&long)), vec!["line_too_long"]);
+        // The rest of the long line is dropped
+        assert_eq!(lines(splitter.push(b"111\n8.8.8.8\n")), vec!["8.8.8.8"]);
+        // A long last line is only reported once
+        assert_eq!(lines(splitter.push(&long)), vec!["line_too_long"]);
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn test_error_item() {
+        let err = ApiError::InvalidAddress(String::from("not-an-address"));
+        let item = error_item(Some(String::from("not-an-address")), &err);
+        assert_eq!(item["address"], "not-an-address");
+        assert_eq!(item["error"]["code"], "invalid_address");
+
+        // A line which could not be read is identified by its line number
+        let mut splitter = LineSplitter::default();
+        splitter.push(b"8.8.8.8\n\n");
+        let err = splitter
+            .push(&[b'1'; MAX_LINE_LENGTH + 1])
+            .remove(0)
+            .unwrap_err();
+        let item = error_item(None, &err);
+        assert!(item.get("address").is_none());
+        assert_eq!(item["line"], 3);
+        assert_eq!(item["error"]["code"], "line_too_long");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let google = "8.8.8.8".parse::<IpAddr>().unwrap();
+        assert_eq!(parse_line("8.8.8.8").unwrap(), google);
+        assert_eq!(
+            parse_line("{\"ip\": \"8.8.8.8\", \"id\": 1}").unwrap(),
+            google
+        );
+        assert_eq!(
+            parse_line("{\"address\": \"8.8.8.8\"}").unwrap_err().code(),
+            "invalid_body"
+        );
+        assert_eq!(
+            parse_line("not-an-address").unwrap_err().code(),
+            "invalid_address"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+// https://docs.rs/serde_json/latest/serde_json/
+use serde_json::{json, Map, Value};
+
+// IP information lookup
+use actix_geo_widget::{
+    LookupAnonymousResult, LookupAsnResult, LookupCityResult, LookupConfidence, LookupIspResult,
+    LookupResult,
+};
+
+use crate::problem::ApiError;
+
+/// Fields of a LookupResult holding a map of any keys, e.g. "datasets.<NAME>.<FIELD>"
+const MAP_FIELDS: &[&str] = &["datasets", "sources"];
+
+/// Return a LookupResult in JSON format with every optional section present
+fn template() -> Value {
+    let address = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    let result = LookupResult {
+        address_encoding: Some(String::new()),
+        isp: Some(LookupIspResult::default()),
+        connection_type: Some(String::new()),
+        anonymous: Some(LookupAnonymousResult::default()),
+        domain: Some(String::new()),
+        confidence: Some(LookupConfidence::default()),
+        tags: vec![String::new()],
+        ..LookupResult::new(
+            address,
+            LookupAsnResult::default(),
+            LookupCityResult::default(),
+        )
+    };
+    json!(result)
+}
+
+/// FieldSelection structure
+///
+/// The fields of a LookupResult to return, from a comma separated list of
+/// (dot separated) field paths, e.g. "country,asn,location.latitude".
+#[derive(Debug, PartialEq)]
+pub struct FieldSelection {
+    paths: Vec<Vec<String>>,
+}
+
+impl FieldSelection {
+    /// Return a FieldSelection, fields which are not part of a LookupResult are an error
+    pub fn parse(fields: &str) -> Result<FieldSelection, ApiError> {
+        let template = template();
+        let mut paths = Vec::new();
+        for field in fields.split(',').map(str::trim) {
+            let path: Vec<String> = field.split('.').map(String::from).collect();
+            if field.is_empty() || !is_known(&template, &path) {
+                return Err(ApiError::InvalidFields(field.to_string()));
+            }
+            paths.push(path);
+        }
+        Ok(FieldSelection { paths })
+    }
+
+    /// Return only the selected fields of a LookupResult in JSON format
+    ///
+    /// Fields missing from the result (e.g. a section of a database which is
+    /// not configured) are left out.
+    pub fn apply(&self, result: &Value) -> Value {
+        let mut selected = Value::Object(Map::new());
+        for path in &self.paths {
+            if let Some(value) = get_path(result, path) {
+                set_path(&mut selected, path, value.clone());
+            }
+        }
+        selected
+    }
+}
+
+/// Return true when a field path is part of the template
+fn is_known(template: &Value, path: &[String]) -> bool {
+    // Any key of a map field is accepted
+    if MAP_FIELDS.contains(&path[0].as_str()) {
+        return true;
+    }
+    let mut current = template;
+    for key in path {
+        match current.get(key) {
+            Some(value) if current.is_object() => current = value,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Return the value at a field path
+fn get_path<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(value, |current, key| current.as_object()?.get(key))
+}
+
+/// Set the value at a field path, adding the objects on the way
+fn set_path(value: &mut Value, path: &[String], field: Value) {
+    let mut current = value;
+    for key in &path[..path.len() - 1] {
+        current = current
+            .as_object_mut()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    current
+        .as_object_mut()
+        .unwrap()
+        .insert(path[path.len() - 1].clone(), field);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_selection() {
+        let result = json!({
+            "address": "8.8.8.8",
+            "asn": 15169,
+            "country": ["US", "United States"],
+            "location": {"latitude": 37.751, "longitude": -97.822},
+            "datasets": {"threats": {"score": 10}},
+        });
+        let fields =
+            FieldSelection::parse("country, asn,location.latitude,datasets.threats.score").unwrap();
+        assert_eq!(
+            fields.apply(&result),
+            json!({
+                "asn": 15169,
+                "country": ["US", "United States"],
+                "location": {"latitude": 37.751},
+                "datasets": {"threats": {"score": 10}},
+            })
+        );
+
+        // Known fields missing from the result are left out
+        let fields = FieldSelection::parse("asn,isp.isp,tags").unwrap();
+        assert_eq!(fields.apply(&result), json!({"asn": 15169}));
+    }
+
+    #[test]
+    fn test_field_selection_unknown() {
+        for fields in [
+            "colour",
+            "asn,",
+            "location.altitude",
+            "asn.number",
+            "country.0",
+        ] {
+            let err = FieldSelection::parse(fields).unwrap_err();
+            assert_eq!(err.code(), "invalid_fields", "{fields:?}");
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
 use std::net::IpAddr;
+use std::sync::{Arc, PoisonError, RwLock};
 
 // A reader for the MaxMind DB format
 // https://docs.rs/maxminddb/latest/maxminddb/
@@ -6,58 +10,579 @@ use std::net::IpAddr;
 // cargo add maxminddb
 use maxminddb::{geoip2, MaxMindDBError, Metadata, Reader};
 
+// Types for IPv4 and IPv6 network addresses
+// https://docs.rs/ipnetwork/latest/ipnetwork/
+// cargo add ipnetwork
+use ipnetwork::IpNetwork;
+
 // https://docs.rs/serde/latest/serde/
 // https://serde.rs
 use serde::{Deserialize, Serialize};
+// https://docs.rs/serde_json/latest/serde_json/
+use serde_json::Value;
+
+// Address parsing and classification
+pub mod address;
+pub mod special;
+pub use address::parse_address;
+pub use special::{
+    classify, effective_address, embedded_ipv4, SpecialPurpose, GLOBAL_SCOPE, SPECIAL_PURPOSE,
+};
+
+// Lookup backends
+pub mod overrides;
+pub mod provider;
+pub use overrides::{OverrideEntry, OverrideProvider, OverrideTable};
+pub use provider::{
+    GeoProvider, MaxMindEditions, MaxMindProvider, ProviderChain, ProviderHealth, ProviderMetadata,
+    CHAIN_FIELDS,
+};
+
+/// GeoError enum
+///
+/// Errors returned by the database and lookup functions. An address which
+/// simply has no record (`AddressNotFound`) is kept apart from the errors
+/// which mean a database is unusable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeoError {
+    /// The database file could not be opened or read
+    MissingFile { file: String, reason: String },
+    /// The database file is not the expected database type
+    WrongDatabaseType { expected: String, found: String },
+    /// The address has no record in the database
+    AddressNotFound(IpAddr),
+    /// The database or a record in it could not be decoded
+    CorruptData(String),
+    /// The database type needed for the lookup is not configured
+    NotConfigured(String),
+    /// The configuration file could not be read or parsed
+    InvalidConfig { file: String, reason: String },
+}
+
+impl fmt::Display for GeoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoError::MissingFile { file, reason } => {
+                write!(f, "Unable to open database file {file}: {reason}")
+            }
+            GeoError::WrongDatabaseType { expected, found } => {
+                write!(
+                    f,
+                    "Wrong database type, expected {expected} but found {found}"
+                )
+            }
+            GeoError::AddressNotFound(addr) => write!(f, "Address {addr} not found in database"),
+            GeoError::CorruptData(reason) => write!(f, "Corrupt database data: {reason}"),
+            GeoError::NotConfigured(database_type) => {
+                write!(f, "No {database_type} database is configured")
+            }
+            GeoError::InvalidConfig { file, reason } => {
+                write!(f, "Invalid configuration file {file}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeoError {}
+
+impl GeoError {
+    /// Return a GeoError for an error raised while opening a database file
+    fn from_open(database_file: &str, error: MaxMindDBError) -> GeoError {
+        match error {
+            MaxMindDBError::IoError(reason) => GeoError::MissingFile {
+                file: database_file.to_string(),
+                reason,
+            },
+            error => GeoError::CorruptData(format!("{database_file}: {error}")),
+        }
+    }
+
+    /// Return a GeoError for an error raised while looking up an address
+    fn from_lookup(addr: IpAddr, error: MaxMindDBError) -> GeoError {
+        match error {
+            MaxMindDBError::AddressNotFoundError(_) => GeoError::AddressNotFound(addr),
+            error => GeoError::CorruptData(error.to_string()),
+        }
+    }
+}
+
+/// Open a database file and check it is the expected database type
+///
+/// `expected` is matched against the database type in the metadata, e.g. "City"
+/// accepts both the "GeoLite2-City" and "GeoIP2-City" editions and
+/// "Connection-Type" accepts "GeoIP2-Connection-Type".
+fn open_database(database_file: &String, expected: &str) -> Result<Reader<Vec<u8>>, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/struct.Reader.html#method.open_readfile
+    let reader = Reader::open_readfile(database_file)
+        .map_err(|error| GeoError::from_open(database_file, error))?;
+
+    // "GeoLite2-ASN" -> "-GeoLite2-ASN-" contains "-ASN-"
+    let database_type = &reader.metadata.database_type;
+    if !format!("-{database_type}-").contains(&format!("-{expected}-")) {
+        return Err(GeoError::WrongDatabaseType {
+            expected: expected.to_string(),
+            found: database_type.to_string(),
+        });
+    }
+
+    Ok(reader)
+}
 
 // Return Metadata about the database
-pub fn lookup_metadata(database_file: &String) -> Metadata {
+pub fn lookup_metadata(database_file: &String) -> Result<Metadata, GeoError> {
     // Create a handle to the GeoLite2-*.mmdb
     // https://oschwald.github.io/maxminddb-rust/maxminddb/struct.Metadata.html
-    let reader = Reader::open_readfile(database_file).unwrap();
+    let reader = Reader::open_readfile(database_file)
+        .map_err(|error| GeoError::from_open(database_file, error))?;
 
     // Return the reader metadata
-    reader.metadata
+    Ok(reader.metadata)
+}
+
+/// DatabaseFiles structure
+///
+/// The database files backing a GeoDatabase. The ASN database is required,
+/// City and Country are optional: when both are configured a City lookup
+/// which has no record falls back to the Country database, and a deployment
+/// shipping only the smaller Country database still gets continent/country.
+/// The commercial GeoIP2 databases (ISP, Connection-Type, Anonymous-IP, Domain
+/// and Enterprise) add their own sections to a LookupResult when configured.
+/// Any other .mmdb files are registered by name in `datasets`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseFiles {
+    pub asn: String,
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub isp: Option<String>,
+    pub connection_type: Option<String>,
+    pub anonymous_ip: Option<String>,
+    pub domain: Option<String>,
+    pub enterprise: Option<String>,
+    pub datasets: BTreeMap<String, DatasetConfig>,
+}
+
+impl DatabaseFiles {
+    /// Return the configured database files
+    pub fn paths(&self) -> Vec<&String> {
+        let mut paths = vec![&self.asn];
+        paths.extend(self.city.iter());
+        paths.extend(self.country.iter());
+        paths.extend(self.isp.iter());
+        paths.extend(self.connection_type.iter());
+        paths.extend(self.anonymous_ip.iter());
+        paths.extend(self.domain.iter());
+        paths.extend(self.enterprise.iter());
+        paths.extend(self.datasets.values().map(|dataset| &dataset.file));
+        paths
+    }
+}
+
+/// DatasetConfig structure
+///
+/// An arbitrary .mmdb file (e.g. DB-IP or IPinfo) and the fields projected
+/// from its records into a LookupResult. Each field maps a name in the response
+/// to a path in the decoded record, either dot separated ("city.names.en",
+/// "subdivisions.0.iso_code") or a JSON Pointer ("/city/names/en").
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DatasetConfig {
+    pub file: String,
+    pub fields: BTreeMap<String, String>,
+    // Seconds after its build date the dataset is stale, never stale when unset
+    #[serde(default)]
+    pub max_age: Option<i64>,
+}
+
+/// Return the datasets declared in a JSON configuration file
+///
+/// ```json
+/// {
+///   "dbip": {
+///     "file": "dbip-city-lite.mmdb",
+///     "fields": { "country": "country.iso_code", "city": "city.names.en" },
+///     "max_age": 2678400
+///   }
+/// }
+/// ```
+pub fn load_datasets(config_file: &str) -> Result<BTreeMap<String, DatasetConfig>, GeoError> {
+    let invalid_config = |reason: String| GeoError::InvalidConfig {
+        file: config_file.to_string(),
+        reason,
+    };
+    let config =
+        fs::read_to_string(config_file).map_err(|error| invalid_config(error.to_string()))?;
+    serde_json::from_str(&config).map_err(|error| invalid_config(error.to_string()))
+}
+
+/// Dataset structure
+///
+/// An opened dataset database and the fields projected from its records
+#[derive(Debug)]
+pub struct Dataset {
+    pub reader: Reader<Vec<u8>>,
+    pub fields: BTreeMap<String, String>,
+    pub max_age: Option<i64>,
+}
+
+/// Open an optional database file and check it is the expected database type
+fn open_optional_database(
+    database_file: &Option<String>,
+    expected: &str,
+) -> Result<Option<Reader<Vec<u8>>>, GeoError> {
+    match database_file {
+        Some(database_file) => Ok(Some(open_database(database_file, expected)?)),
+        None => Ok(None),
+    }
+}
+
+/// GeoDatabase structure
+///
+/// Holds the database readers open for the lifetime of the process so
+/// lookups do not re-read the .mmdb files from disk on every request.
+/// `Reader<Vec<u8>>` is `Send + Sync`, so a single handle can be shared
+/// between all of the server workers (e.g. behind an `Arc`).
+#[derive(Debug)]
+pub struct GeoDatabase {
+    pub asn_reader: Reader<Vec<u8>>,
+    pub city_reader: Option<Reader<Vec<u8>>>,
+    pub country_reader: Option<Reader<Vec<u8>>>,
+    pub isp_reader: Option<Reader<Vec<u8>>>,
+    pub connection_type_reader: Option<Reader<Vec<u8>>>,
+    pub anonymous_ip_reader: Option<Reader<Vec<u8>>>,
+    pub domain_reader: Option<Reader<Vec<u8>>>,
+    pub enterprise_reader: Option<Reader<Vec<u8>>>,
+    pub datasets: BTreeMap<String, Dataset>,
+}
+
+impl GeoDatabase {
+    /// Open the ASN and City databases once and return a GeoDatabase handle
+    pub fn open(
+        asn_database_file: &str,
+        city_database_file: &str,
+    ) -> Result<GeoDatabase, GeoError> {
+        GeoDatabase::open_files(&DatabaseFiles {
+            asn: asn_database_file.to_string(),
+            city: Some(city_database_file.to_string()),
+            ..DatabaseFiles::default()
+        })
+    }
+
+    /// Open the configured databases once and return a GeoDatabase handle
+    pub fn open_files(files: &DatabaseFiles) -> Result<GeoDatabase, GeoError> {
+        // Create a handle to the GeoLite2-ASN.mmdb, GeoLite2-City.mmdb and GeoLite2-Country.mmdb
+        let asn_reader = open_database(&files.asn, "ASN")?;
+        let city_reader = open_optional_database(&files.city, "City")?;
+        let country_reader = open_optional_database(&files.country, "Country")?;
+
+        // Create a handle to the commercial GeoIP2-*.mmdb (when configured)
+        let isp_reader = open_optional_database(&files.isp, "ISP")?;
+        let connection_type_reader =
+            open_optional_database(&files.connection_type, "Connection-Type")?;
+        let anonymous_ip_reader = open_optional_database(&files.anonymous_ip, "Anonymous-IP")?;
+        let domain_reader = open_optional_database(&files.domain, "Domain")?;
+        let enterprise_reader = open_optional_database(&files.enterprise, "Enterprise")?;
+
+        // Create a handle to each dataset, any database type is accepted
+        let mut datasets = BTreeMap::new();
+        for (name, dataset) in &files.datasets {
+            let reader = Reader::open_readfile(&dataset.file)
+                .map_err(|error| GeoError::from_open(&dataset.file, error))?;
+            datasets.insert(
+                name.clone(),
+                Dataset {
+                    reader,
+                    fields: dataset.fields.clone(),
+                    max_age: dataset.max_age,
+                },
+            );
+        }
+
+        Ok(GeoDatabase {
+            asn_reader,
+            city_reader,
+            country_reader,
+            isp_reader,
+            connection_type_reader,
+            anonymous_ip_reader,
+            domain_reader,
+            enterprise_reader,
+            datasets,
+        })
+    }
+
+    /// Return Metadata about the ASN database
+    pub fn asn_metadata(&self) -> &Metadata {
+        &self.asn_reader.metadata
+    }
+
+    /// Return Metadata about the City database (when configured)
+    pub fn city_metadata(&self) -> Option<&Metadata> {
+        self.city_reader.as_ref().map(|reader| &reader.metadata)
+    }
+
+    /// Return Metadata about the Country database (when configured)
+    pub fn country_metadata(&self) -> Option<&Metadata> {
+        self.country_reader.as_ref().map(|reader| &reader.metadata)
+    }
+
+    /// Return Metadata about the Enterprise database (when configured)
+    pub fn enterprise_metadata(&self) -> Option<&Metadata> {
+        self.enterprise_reader
+            .as_ref()
+            .map(|reader| &reader.metadata)
+    }
+
+    /// Return Metadata about all of the configured databases, the MaxMind editions then the datasets
+    pub fn metadata(&self) -> Vec<&Metadata> {
+        let mut metadata = self.edition_metadata();
+        for dataset in self.datasets.values() {
+            metadata.push(&dataset.reader.metadata);
+        }
+        metadata
+    }
+
+    /// Return Metadata about the configured MaxMind editions (GeoLite2 and GeoIP2)
+    pub fn edition_metadata(&self) -> Vec<&Metadata> {
+        let mut metadata = vec![self.asn_metadata()];
+        metadata.extend(self.city_metadata());
+        metadata.extend(self.country_metadata());
+        let commercial_readers = [
+            &self.isp_reader,
+            &self.connection_type_reader,
+            &self.anonymous_ip_reader,
+            &self.domain_reader,
+            &self.enterprise_reader,
+        ];
+        for reader in commercial_readers.into_iter().flatten() {
+            metadata.push(&reader.metadata);
+        }
+        metadata
+    }
+
+    /// Return the locales names are available in
+    pub fn languages(&self) -> &[String] {
+        match self.city_metadata().or(self.country_metadata()) {
+            Some(metadata) => &metadata.languages,
+            None => &[],
+        }
+    }
+}
+
+/// SharedGeoDatabase structure
+///
+/// Wraps a GeoDatabase so it can be replaced while the server is running.
+/// Lookups take a snapshot with `load()` and keep using it until they finish,
+/// so swapping in a new database never interrupts an in-flight request.
+/// When the databases could not be opened the error is held instead and
+/// returned by `load()` until a database is stored.
+#[derive(Debug)]
+pub struct SharedGeoDatabase {
+    database: RwLock<Result<Arc<GeoDatabase>, GeoError>>,
+}
+
+impl SharedGeoDatabase {
+    /// Return a SharedGeoDatabase holding an opened GeoDatabase
+    pub fn new(database: GeoDatabase) -> SharedGeoDatabase {
+        SharedGeoDatabase {
+            database: RwLock::new(Ok(Arc::new(database))),
+        }
+    }
+
+    /// Return a SharedGeoDatabase without a database, `load()` returns `error`
+    pub fn unavailable(error: GeoError) -> SharedGeoDatabase {
+        SharedGeoDatabase {
+            database: RwLock::new(Err(error)),
+        }
+    }
+
+    /// Return a snapshot of the current GeoDatabase
+    pub fn load(&self) -> Result<Arc<GeoDatabase>, GeoError> {
+        // A poisoned lock still holds a valid value, the writer only ever swaps it
+        let database = self.database.read().unwrap_or_else(PoisonError::into_inner);
+        database.clone()
+    }
+
+    /// Atomically replace the current GeoDatabase, returning the previous one
+    pub fn store(&self, database: GeoDatabase) -> Option<Arc<GeoDatabase>> {
+        let mut current = self
+            .database
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Ok(Arc::new(database))).ok()
+    }
+}
+
+/// Default locale for names, every MaxMind database includes English names
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Return the chain of locales used to look up names
+///
+/// Each requested locale (most preferred first) is matched against the locales
+/// available in the database, exactly and then by its primary language when
+/// either side has no region, e.g. "de-AT" -> "de" and "pt" -> "pt-BR", but not
+/// "zh-TW" -> "zh-CN" or "pt-PT" -> "pt-BR". The default locale always ends the chain.
+pub fn locale_chain(requested: &[String], available: &[String]) -> Vec<String> {
+    let primary_language = |locale: &str| locale.split('-').next().unwrap_or_default().to_string();
+    let has_region = |locale: &str| locale.contains('-');
+
+    let mut chain: Vec<String> = Vec::new();
+    for locale in requested {
+        let matched = available
+            .iter()
+            .find(|available| available.eq_ignore_ascii_case(locale))
+            .or_else(|| {
+                available.iter().find(|available| {
+                    (!has_region(locale) || !has_region(available))
+                        && primary_language(available)
+                            .eq_ignore_ascii_case(&primary_language(locale))
+                })
+            });
+        if let Some(matched) = matched {
+            if !chain.contains(matched) {
+                chain.push(matched.clone());
+            }
+        }
+    }
+    if !chain.iter().any(|locale| locale == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+    chain
+}
+
+/// Reads names from MaxMind names maps using a locale chain
+struct LocaleNames<'a> {
+    chain: &'a [String],
+    // The most preferred locale a name was found in and its position in the chain
+    // (after the chain when the locale is not part of it)
+    found: Option<(usize, String)>,
+    // Set when a name was only found in a later locale of the chain
+    fallback: bool,
 }
 
-/// LookupCountryResult structure
+impl<'a> LocaleNames<'a> {
+    fn new(chain: &'a [String]) -> LocaleNames<'a> {
+        LocaleNames {
+            chain,
+            found: None,
+            fallback: false,
+        }
+    }
+
+    /// Return the name for the first locale in the chain found in a names map
+    ///
+    /// A record without any names returns "-", a record with names but none for
+    /// the locales in the chain returns the name of any other locale (a fallback)
+    /// rather than failing the lookup.
+    fn get(&mut self, names: Option<&BTreeMap<&str, &str>>) -> String {
+        let names = match names {
+            None => return String::from("-"),
+            Some(names) => names,
+        };
+        for (index, locale) in self.chain.iter().enumerate() {
+            if let Some(name) = names.get(locale.as_str()) {
+                self.found(index, locale);
+                return name.to_string();
+            }
+        }
+        match names.iter().next() {
+            Some((locale, name)) => {
+                self.found(self.chain.len(), locale);
+                name.to_string()
+            }
+            None => String::from("-"),
+        }
+    }
+
+    fn found(&mut self, index: usize, locale: &str) {
+        self.fallback |= index > 0;
+        if self.found.as_ref().is_none_or(|(found, _)| index < *found) {
+            self.found = Some((index, locale.to_string()));
+        }
+    }
+
+    /// Return the locale the names were found in, the most preferred one when
+    /// they were found in several, or the first of the chain without any names
+    fn locale(&self) -> String {
+        match &self.found {
+            Some((_, locale)) => locale.clone(),
+            None => self.chain[0].clone(),
+        }
+    }
+}
+
+/// Return the ISO 3166-2 code of a subdivision including the country
+///
+/// e.g. ("GB", "ENG") -> "GB-ENG", the bare subdivision code (or "-") when the country is unknown
+fn get_full_iso_code(country: &str, iso_code: &str) -> String {
+    match (country, iso_code) {
+        ("-", iso_code) | (_, iso_code @ "-") => iso_code.to_string(),
+        (country, iso_code) => format!("{country}-{iso_code}"),
+    }
+}
+
+/// Return the network (CIDR) an address was found in from its prefix length
+///
+/// e.g. 4.3.2.1 with a prefix length of 9 -> ("4.0.0.0/9", Some(9))
+fn get_network(addr: IpAddr, prefix_len: usize) -> (String, Option<u8>) {
+    // https://docs.rs/ipnetwork/latest/ipnetwork/enum.IpNetwork.html
+    let prefix = match u8::try_from(prefix_len) {
+        Ok(prefix) => prefix,
+        Err(_) => return (String::from("-"), None),
+    };
+    match IpNetwork::new(addr, prefix).and_then(|net| IpNetwork::new(net.network(), prefix)) {
+        Ok(network) => (network.to_string(), Some(prefix)),
+        Err(_) => (String::from("-"), None),
+    }
+}
 
 // LookupAsnResult structure
 #[derive(Clone, Debug, Deserialize)]
 pub struct LookupAsnResult {
     pub asn: u32,
     pub asn_organization: String,
+    // The network the ASN record was found in
+    pub network: String,
+    pub prefix_len: Option<u8>,
+}
+
+impl Default for LookupAsnResult {
+    fn default() -> Self {
+        LookupAsnResult {
+            asn: 0,
+            asn_organization: String::from("-"),
+            network: String::from("-"),
+            prefix_len: None,
+        }
+    }
 }
 
 /// Return a LookupAsnResult structure for an IP address
 pub fn lookup_asn(
-    asn_database_file: &String,
+    database: &GeoDatabase,
     addr: IpAddr,
     debug: bool,
     verbose: bool,
-) -> LookupAsnResult {
-    // Default values to be used on any error
-    let asn_result_default = LookupAsnResult {
-        asn: 0,
-        asn_organization: String::from("-"),
-    };
-
-    // Create a handle to the GeoLite2-ASN.mmdb
+) -> Result<LookupAsnResult, GeoError> {
+    // Lookup the ASN information for the IP address using the shared GeoLite2-ASN.mmdb handle
     // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/index.html
     // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Asn.html
-    let reader = Reader::open_readfile(asn_database_file).unwrap();
-
-    // Lookup the ASN information for the IP address
-    let asn_lookup_result: Result<geoip2::Asn, MaxMindDBError> = reader.lookup(addr);
+    let asn_lookup_result: Result<(geoip2::Asn, usize), MaxMindDBError> =
+        database.asn_reader.lookup_prefix(addr);
 
-    // Handle lookup errors gracefully
-    // Unwrap a result or use the default value
+    // Convert lookup errors into a GeoError
+    // Fields missing from the record use the default values
     // Return the result
     match asn_lookup_result {
-        Ok(result) => LookupAsnResult {
-            asn: result.autonomous_system_number.unwrap(),
-            asn_organization: String::from(result.autonomous_system_organization.unwrap()),
-        },
+        Ok((result, prefix_len)) => {
+            let (network, prefix_len) = get_network(addr, prefix_len);
+            Ok(LookupAsnResult {
+                asn: result.autonomous_system_number.unwrap_or(0),
+                asn_organization: String::from(
+                    result.autonomous_system_organization.unwrap_or("-"),
+                ),
+                network,
+                prefix_len,
+            })
+        }
         Err(error) => {
             if debug {
                 println!("lookup_asn(addr: {addr:#?}) error: {error:#?}");
@@ -65,127 +590,280 @@ pub fn lookup_asn(
             if verbose {
                 //TODO:
             }
-            asn_result_default
+            Err(GeoError::from_lookup(addr, error))
         }
     }
 }
 
+/// LookupLocation structure
+///
+/// The approximate location of an address, fields missing from the record are `None`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LookupLocation {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // Radius in kilometers around the latitude/longitude
+    pub accuracy_radius: Option<u16>,
+    pub time_zone: Option<String>,
+    pub metro_code: Option<u16>,
+}
+
+/// LookupSubdivision structure
+///
+/// A subdivision of a country, e.g. England ("ENG") in the United Kingdom
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LookupSubdivision {
+    pub iso_code: String,
+    pub name: String,
+    // ISO 3166-2 code including the country, e.g. "GB-ENG"
+    pub full_iso_code: String,
+}
+
 /// LookupCityResult structure
 #[derive(Clone, Debug, Deserialize)]
 pub struct LookupCityResult {
     pub city: String,
     pub continent: (String, String),
     pub country: (String, String),
-    pub subdivisions: (String, String),
+    // Ordered from the largest to the most specific subdivision
+    pub subdivisions: Vec<LookupSubdivision>,
+    pub location: LookupLocation,
+    pub postal: String,
+    // The country the network is registered to, which may differ from `country`
+    pub registered_country: (String, String),
+    // The country represented by users of the network (e.g. an overseas military base)
+    pub represented_country: (String, String),
+    pub represented_country_type: String,
+    pub is_anycast: bool,
+    pub is_anonymous_proxy: bool,
+    pub is_satellite_provider: bool,
+    // The network the City record was found in
+    pub network: String,
+    pub prefix_len: Option<u8>,
+    // The locale the names were found in, `locale_fallback` is set when
+    // any name was only available in a later locale of the chain
+    pub locale: String,
+    pub locale_fallback: bool,
+}
+
+impl Default for LookupCityResult {
+    fn default() -> Self {
+        LookupCityResult {
+            city: String::from("-"),
+            continent: (String::from("-"), String::from("-")),
+            country: (String::from("-"), String::from("-")),
+            subdivisions: Vec::new(),
+            location: LookupLocation::default(),
+            postal: String::from("-"),
+            registered_country: (String::from("-"), String::from("-")),
+            represented_country: (String::from("-"), String::from("-")),
+            represented_country_type: String::from("-"),
+            is_anycast: false,
+            is_anonymous_proxy: false,
+            is_satellite_provider: false,
+            network: String::from("-"),
+            prefix_len: None,
+            locale: DEFAULT_LOCALE.to_string(),
+            locale_fallback: false,
+        }
+    }
 }
 
 /// Return a LookupCityResult structure for an IP address
+///
+/// Names are returned in the first of the `locales` (most preferred first)
+/// available in the database, falling back to the default locale.
 pub fn lookup_city(
-    city_database_file: &String,
+    database: &GeoDatabase,
     addr: IpAddr,
+    locales: &[String],
     debug: bool,
     verbose: bool,
-) -> LookupCityResult {
-    // Default values to be used on any error
-    let city_result_default = LookupCityResult {
-        city: String::from("-"),
-        continent: (String::from("-"), String::from("-")),
-        country: (String::from("-"), String::from("-")),
-        subdivisions: (String::from("-"), String::from("-")),
+) -> Result<LookupCityResult, GeoError> {
+    let city_reader = match &database.city_reader {
+        Some(city_reader) => city_reader,
+        None => return Err(GeoError::NotConfigured(String::from("City"))),
     };
 
-    // Create a handle to the GeoLite2-City.mmdb
+    // Lookup the City information for the IP address using the shared GeoLite2-City.mmdb handle
     // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/index.html
-    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Asn.html
-    let geo_lite2_city_reader = Reader::open_readfile(city_database_file).unwrap();
-
-    // Lookup the City information for the IP address
-    let city_lookup_result: Result<geoip2::City, MaxMindDBError> =
-        geo_lite2_city_reader.lookup(addr);
-
-    // Handle lookup errors gracefully
-    // Unwrap a result or use the default value
-    let city_result = match city_lookup_result {
-        Ok(result) => {
-            // <Result>.city -> String
-            let city = match result.city {
-                None => String::from("-"),
-                // TODO: needs a cleaner method like: `result.city?.names.get("en", "-")`
-                _ => result
-                    .city
-                    .unwrap()
-                    .names
-                    .unwrap()
-                    .get("en")
-                    .unwrap()
-                    .to_string(),
-            };
-
-            // <Result>.continent -> (String, String)
-            let continent = match result.continent {
-                None => (String::from("-"), String::from("-")),
-                _ => {
-                    let continent = result.continent.unwrap();
-                    (
-                        continent.code.unwrap().to_string(),
-                        continent.names.unwrap().get("en").unwrap().to_string(),
-                    )
-                }
-            };
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.City.html
+    let city_lookup_result: Result<(geoip2::City, usize), MaxMindDBError> =
+        city_reader.lookup_prefix(addr);
 
-            // <Result>.country -> (String, String)
-            let country = match result.country {
-                None => (String::from("-"), String::from("-")),
-                _ => {
-                    let country = result.country.unwrap();
-                    (
-                        country.iso_code.unwrap().to_string(),
-                        country.names.unwrap().get("en").unwrap().to_string(),
-                    )
-                }
-            };
+    // Convert lookup errors into a GeoError
+    match city_lookup_result {
+        Ok((result, prefix_len)) => Ok(get_city_result(
+            addr,
+            result,
+            prefix_len,
+            locales,
+            database.languages(),
+        )),
+        Err(error) => {
+            if debug {
+                println!("lookup_city(addr: {addr:#?}) error: {error:#?}");
+            }
+            if verbose {
+                //TODO:
+            }
+            Err(GeoError::from_lookup(addr, error))
+        }
+    }
+}
 
-            // <Result>.subdivisions -> (String, String)
-            let subdivisions = match result.subdivisions {
-                None => (String::from("-"), String::from("-")),
-                _ => {
-                    let subdivisions = result.subdivisions.unwrap();
-                    (
-                        subdivisions[0].iso_code.unwrap().to_string(),
-                        subdivisions[0]
-                            .names
-                            .as_ref()
-                            .unwrap()
-                            .get("en")
-                            .unwrap()
-                            .to_string(),
-                    )
-                }
-            };
+/// Return a LookupCityResult structure for an IP address from the Country database
+///
+/// Only the continent, country and traits are set, the Country database has no
+/// city, subdivision, location or postal information.
+pub fn lookup_country(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupCityResult, GeoError> {
+    let country_reader = match &database.country_reader {
+        Some(country_reader) => country_reader,
+        None => return Err(GeoError::NotConfigured(String::from("Country"))),
+    };
 
-            // These fields exist in the data but are not used here
-            // <Result>.location
-            // <Result>.postal
-            // <Result>.registered_country
-            // <Result>.traits
+    // Lookup the Country information for the IP address using the shared GeoLite2-Country.mmdb handle
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Country.html
+    let country_lookup_result: Result<(geoip2::Country, usize), MaxMindDBError> =
+        country_reader.lookup_prefix(addr);
 
-            LookupCityResult {
-                city,
-                continent,
-                country,
-                subdivisions,
-            }
+    // Convert lookup errors into a GeoError
+    // The Country record is a subset of the City record
+    match country_lookup_result {
+        Ok((result, prefix_len)) => {
+            let result = geoip2::City {
+                city: None,
+                continent: result.continent,
+                country: result.country,
+                location: None,
+                postal: None,
+                registered_country: result.registered_country,
+                represented_country: result.represented_country,
+                subdivisions: None,
+                traits: result.traits,
+            };
+            Ok(get_city_result(
+                addr,
+                result,
+                prefix_len,
+                locales,
+                database.languages(),
+            ))
         }
         Err(error) => {
             if debug {
-                println!("lookup_city(addr: {addr:#?}) error: {error:#?}");
+                println!("lookup_country(addr: {addr:#?}) error: {error:#?}");
             }
             if verbose {
                 //TODO:
             }
-            city_result_default
+            Err(GeoError::from_lookup(addr, error))
         }
-    };
+    }
+}
+
+/// Return a LookupCityResult structure from a City record, with names in the
+/// `languages` of the database it was found in
+///
+/// Never fails, a record without a name in any of the `locales` uses a name in
+/// another locale (see `LocaleNames`).
+fn get_city_result(
+    addr: IpAddr,
+    result: geoip2::City,
+    prefix_len: usize,
+    locales: &[String],
+    languages: &[String],
+) -> LookupCityResult {
+    // Fields missing from the record use the default values
+    let mut city_result = LookupCityResult::default();
+    (city_result.network, city_result.prefix_len) = get_network(addr, prefix_len);
+
+    // Names are looked up through the locale chain
+    let chain = locale_chain(locales, languages);
+    let mut names = LocaleNames::new(&chain);
+
+    // <Result>.city -> String
+    if let Some(city) = result.city {
+        city_result.city = names.get(city.names.as_ref());
+    }
+
+    // <Result>.continent -> (String, String)
+    if let Some(continent) = result.continent {
+        city_result.continent = (
+            continent.code.unwrap_or("-").to_string(),
+            names.get(continent.names.as_ref()),
+        );
+    }
+
+    // <Result>.country -> (String, String)
+    if let Some(country) = result.country {
+        city_result.country = (
+            country.iso_code.unwrap_or("-").to_string(),
+            names.get(country.names.as_ref()),
+        );
+    }
+
+    // <Result>.subdivisions -> Vec<LookupSubdivision>
+    for subdivision in result.subdivisions.iter().flatten() {
+        let iso_code = subdivision.iso_code.unwrap_or("-").to_string();
+        city_result.subdivisions.push(LookupSubdivision {
+            full_iso_code: get_full_iso_code(&city_result.country.0, &iso_code),
+            iso_code,
+            name: names.get(subdivision.names.as_ref()),
+        });
+    }
+
+    // <Result>.location -> LookupLocation
+    if let Some(location) = result.location {
+        city_result.location = LookupLocation {
+            latitude: location.latitude,
+            longitude: location.longitude,
+            accuracy_radius: location.accuracy_radius,
+            time_zone: location.time_zone.map(String::from),
+            metro_code: location.metro_code,
+        };
+    }
+
+    // <Result>.postal -> String
+    if let Some(postal) = result.postal {
+        city_result.postal = postal.code.unwrap_or("-").to_string();
+    }
+
+    // <Result>.registered_country -> (String, String)
+    if let Some(registered_country) = result.registered_country {
+        city_result.registered_country = (
+            registered_country.iso_code.unwrap_or("-").to_string(),
+            names.get(registered_country.names.as_ref()),
+        );
+    }
+
+    // <Result>.represented_country -> (String, String)
+    if let Some(represented_country) = result.represented_country {
+        city_result.represented_country = (
+            represented_country.iso_code.unwrap_or("-").to_string(),
+            names.get(represented_country.names.as_ref()),
+        );
+        city_result.represented_country_type = represented_country
+            .representation_type
+            .unwrap_or("-")
+            .to_string();
+    }
+
+    // <Result>.traits -> bool flags
+    if let Some(traits) = result.traits {
+        city_result.is_anycast = traits.is_anycast.unwrap_or(false);
+        city_result.is_anonymous_proxy = traits.is_anonymous_proxy.unwrap_or(false);
+        city_result.is_satellite_provider = traits.is_satellite_provider.unwrap_or(false);
+    }
+
+    city_result.locale = names.locale();
+    city_result.locale_fallback = names.fallback;
 
     // Return the result
     city_result
@@ -195,24 +873,157 @@ pub fn lookup_city(
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct LookupResult {
     pub address: IpAddr,
+    // The address looked up, the IPv4 address embedded in an IPv4-mapped, NAT64,
+    // 6to4 or Teredo address (named by `address_encoding`) or else the address
+    pub effective_address: IpAddr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_encoding: Option<String>,
+    // Scope of the effective address, e.g. "global", "private", "loopback" (see `special::classify`)
+    pub scope: String,
+    // True when the address is in an IANA special-purpose range which is not globally reachable
+    pub reserved: bool,
     pub asn: u32,
     pub asn_organization: String,
     pub city: String,
     pub continent: (String, String),
     pub country: (String, String),
-    pub subdivisions: (String, String),
+    pub subdivisions: Vec<LookupSubdivision>,
+    pub location: LookupLocation,
+    pub postal: String,
+    pub registered_country: (String, String),
+    pub represented_country: (String, String),
+    pub represented_country_type: String,
+    pub is_anycast: bool,
+    pub is_anonymous_proxy: bool,
+    pub is_satellite_provider: bool,
+    pub asn_network: String,
+    pub asn_prefix_len: Option<u8>,
+    pub city_network: String,
+    pub city_prefix_len: Option<u8>,
+    pub locale: String,
+    pub locale_fallback: bool,
+    // Sections from the commercial databases, omitted when not configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp: Option<LookupIspResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anonymous: Option<LookupAnonymousResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<LookupConfidence>,
+    // Fields projected from each dataset, omitted when none are configured
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub datasets: BTreeMap<String, BTreeMap<String, Value>>,
+    // Tags of the matching override entry, omitted when there are none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // The provider which supplied each field (see `ProviderChain`), omitted when not chained
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, String>,
     pub summary: String,
 }
 
+impl LookupResult {
+    /// Return a LookupResult structure for an IP address from its ASN and City results
+    ///
+    /// The commercial sections and datasets are left empty.
+    pub fn new(address: IpAddr, asn: LookupAsnResult, city: LookupCityResult) -> LookupResult {
+        let summary = get_summary(&asn, &city);
+        let embedded = embedded_ipv4(address);
+        let effective_address = embedded.map_or(address, |(ipv4, _)| IpAddr::V4(ipv4));
+        let special = classify(effective_address);
+        LookupResult {
+            address,
+            effective_address,
+            address_encoding: embedded.map(|(_, encoding)| encoding.to_string()),
+            scope: special
+                .map_or(GLOBAL_SCOPE, |special| special.scope)
+                .to_string(),
+            reserved: special.is_some_and(|special| !special.globally_reachable),
+            asn: asn.asn,
+            asn_organization: asn.asn_organization,
+            city: city.city,
+            continent: city.continent,
+            country: city.country,
+            subdivisions: city.subdivisions,
+            location: city.location,
+            postal: city.postal,
+            registered_country: city.registered_country,
+            represented_country: city.represented_country,
+            represented_country_type: city.represented_country_type,
+            is_anycast: city.is_anycast,
+            is_anonymous_proxy: city.is_anonymous_proxy,
+            is_satellite_provider: city.is_satellite_provider,
+            asn_network: asn.network,
+            asn_prefix_len: asn.prefix_len,
+            city_network: city.network,
+            city_prefix_len: city.prefix_len,
+            locale: city.locale,
+            locale_fallback: city.locale_fallback,
+            isp: None,
+            connection_type: None,
+            anonymous: None,
+            domain: None,
+            confidence: None,
+            datasets: BTreeMap::new(),
+            tags: Vec::new(),
+            sources: BTreeMap::new(),
+            summary,
+        }
+    }
+
+    /// Recompute the summary from the fields of the result
+    pub fn update_summary(&mut self) {
+        let asn = LookupAsnResult {
+            asn: self.asn,
+            asn_organization: self.asn_organization.clone(),
+            ..LookupAsnResult::default()
+        };
+        let city = LookupCityResult {
+            city: self.city.clone(),
+            country: self.country.clone(),
+            subdivisions: self.subdivisions.clone(),
+            registered_country: self.registered_country.clone(),
+            represented_country: self.represented_country.clone(),
+            represented_country_type: self.represented_country_type.clone(),
+            ..LookupCityResult::default()
+        };
+        self.summary = get_summary(&asn, &city);
+    }
+}
+
 /// Return a Lookup summary structure
 pub fn get_summary(asn: &LookupAsnResult, city: &LookupCityResult) -> String {
     // "<CITY>,<STATE>/<COUNTRY>; <AS NAME> (<ASN>);"
+    // <STATE> is the most specific subdivision
+    // The registered and represented countries are added after the country
+    // when they are known and differ from it:
+    // "<CITY>,<STATE>/<COUNTRY> (registered: <COUNTRY>) (represented: <COUNTRY> <TYPE>); ..."
     let mut summary = String::new();
     summary.push_str(&city.city);
     summary.push(',');
-    summary.push_str(&city.subdivisions.0);
+    match city.subdivisions.last() {
+        Some(subdivision) => summary.push_str(&subdivision.iso_code),
+        None => summary.push('-'),
+    }
     summary.push('/');
     summary.push_str(&city.country.0);
+    let registered = &city.registered_country.0;
+    if registered != "-" && registered != &city.country.0 {
+        summary.push_str(" (registered: ");
+        summary.push_str(registered);
+        summary.push(')');
+    }
+    let represented = &city.represented_country.0;
+    if represented != "-" && represented != &city.country.0 {
+        summary.push_str(" (represented: ");
+        summary.push_str(represented);
+        summary.push(' ');
+        summary.push_str(&city.represented_country_type);
+        summary.push(')');
+    }
     summary.push_str("; ");
     summary.push_str(&asn.asn_organization);
     summary.push_str(" (");
@@ -221,26 +1032,923 @@ pub fn get_summary(asn: &LookupAsnResult, city: &LookupCityResult) -> String {
     summary
 }
 
+/// Return the record for an IP address from an optional database
+fn lookup_record<'a, T: Deserialize<'a>>(
+    reader: &'a Option<Reader<Vec<u8>>>,
+    database_type: &str,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<T, GeoError> {
+    let reader = match reader {
+        Some(reader) => reader,
+        None => return Err(GeoError::NotConfigured(database_type.to_string())),
+    };
+
+    // Convert lookup errors into a GeoError
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/struct.Reader.html#method.lookup
+    reader.lookup(addr).map_err(|error| {
+        if debug {
+            println!(
+                "lookup_record(addr: {addr:#?}, database_type: {database_type}) error: {error:#?}"
+            );
+        }
+        if verbose {
+            //TODO:
+        }
+        GeoError::from_lookup(addr, error)
+    })
+}
+
+/// LookupIspResult structure
+///
+/// From the GeoIP2-ISP database
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LookupIspResult {
+    pub isp: String,
+    pub organization: String,
+    pub asn: u32,
+    pub asn_organization: String,
+    // Mobile country and network codes (MCC/MNC) for cellular networks
+    pub mobile_country_code: String,
+    pub mobile_network_code: String,
+}
+
+impl Default for LookupIspResult {
+    fn default() -> Self {
+        LookupIspResult {
+            isp: String::from("-"),
+            organization: String::from("-"),
+            asn: 0,
+            asn_organization: String::from("-"),
+            mobile_country_code: String::from("-"),
+            mobile_network_code: String::from("-"),
+        }
+    }
+}
+
+/// Return a LookupIspResult structure for an IP address
+pub fn lookup_isp(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupIspResult, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Isp.html
+    let result: geoip2::Isp = lookup_record(&database.isp_reader, "ISP", addr, debug, verbose)?;
+    Ok(LookupIspResult {
+        isp: result.isp.unwrap_or("-").to_string(),
+        organization: result.organization.unwrap_or("-").to_string(),
+        asn: result.autonomous_system_number.unwrap_or(0),
+        asn_organization: result
+            .autonomous_system_organization
+            .unwrap_or("-")
+            .to_string(),
+        mobile_country_code: result.mobile_country_code.unwrap_or("-").to_string(),
+        mobile_network_code: result.mobile_network_code.unwrap_or("-").to_string(),
+    })
+}
+
+/// Return the connection type for an IP address, e.g. "Cable/DSL" or "Cellular"
+pub fn lookup_connection_type(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<String, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.ConnectionType.html
+    let result: geoip2::ConnectionType = lookup_record(
+        &database.connection_type_reader,
+        "Connection-Type",
+        addr,
+        debug,
+        verbose,
+    )?;
+    Ok(result.connection_type.unwrap_or("-").to_string())
+}
+
+/// LookupAnonymousResult structure
+///
+/// From the GeoIP2-Anonymous-IP database, flags missing from the record are `false`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LookupAnonymousResult {
+    pub is_anonymous: bool,
+    pub is_vpn: bool,
+    pub is_tor: bool,
+    pub is_hosting: bool,
+    pub is_public_proxy: bool,
+    pub is_residential_proxy: bool,
+}
+
+/// Return a LookupAnonymousResult structure for an IP address
+pub fn lookup_anonymous_ip(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupAnonymousResult, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.AnonymousIp.html
+    let result: geoip2::AnonymousIp = lookup_record(
+        &database.anonymous_ip_reader,
+        "Anonymous-IP",
+        addr,
+        debug,
+        verbose,
+    )?;
+    Ok(LookupAnonymousResult {
+        is_anonymous: result.is_anonymous.unwrap_or(false),
+        is_vpn: result.is_anonymous_vpn.unwrap_or(false),
+        is_tor: result.is_tor_exit_node.unwrap_or(false),
+        is_hosting: result.is_hosting_provider.unwrap_or(false),
+        is_public_proxy: result.is_public_proxy.unwrap_or(false),
+        is_residential_proxy: result.is_residential_proxy.unwrap_or(false),
+    })
+}
+
+/// Return the second level domain for an IP address, e.g. "example.com"
+pub fn lookup_domain(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<String, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Domain.html
+    let result: geoip2::Domain =
+        lookup_record(&database.domain_reader, "Domain", addr, debug, verbose)?;
+    Ok(result.domain.unwrap_or("-").to_string())
+}
+
+/// LookupConfidence structure
+///
+/// From the GeoIP2-Enterprise database, the confidence (0-100) that the
+/// city, country, postal code and subdivisions are correct
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LookupConfidence {
+    pub city: Option<u8>,
+    pub country: Option<u8>,
+    pub postal: Option<u8>,
+    // Ordered the same as the subdivisions
+    pub subdivisions: Vec<Option<u8>>,
+}
+
+/// Return a LookupConfidence structure for an IP address
+pub fn lookup_confidence(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupConfidence, GeoError> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Enterprise.html
+    let result: geoip2::Enterprise = lookup_record(
+        &database.enterprise_reader,
+        "Enterprise",
+        addr,
+        debug,
+        verbose,
+    )?;
+    Ok(get_confidence(result))
+}
+
+/// Return a LookupConfidence structure from an Enterprise record
+fn get_confidence(result: geoip2::Enterprise) -> LookupConfidence {
+    LookupConfidence {
+        city: result.city.and_then(|city| city.confidence),
+        country: result.country.and_then(|country| country.confidence),
+        postal: result.postal.and_then(|postal| postal.confidence),
+        subdivisions: result
+            .subdivisions
+            .iter()
+            .flatten()
+            .map(|subdivision| subdivision.confidence)
+            .collect(),
+    }
+}
+
+/// Return the City record held in an Enterprise record, without the confidence scores
+fn get_enterprise_city<'a>(result: &geoip2::Enterprise<'a>) -> geoip2::City<'a> {
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/enterprise/index.html
+    let country = |country: &geoip2::enterprise::Country<'a>| geoip2::city::Country {
+        geoname_id: country.geoname_id,
+        is_in_european_union: country.is_in_european_union,
+        iso_code: country.iso_code,
+        names: country.names.clone(),
+    };
+    geoip2::City {
+        city: result.city.as_ref().map(|city| geoip2::city::City {
+            geoname_id: city.geoname_id,
+            names: city.names.clone(),
+        }),
+        continent: result.continent.clone(),
+        country: result.country.as_ref().map(country),
+        location: result
+            .location
+            .as_ref()
+            .map(|location| geoip2::city::Location {
+                accuracy_radius: location.accuracy_radius,
+                latitude: location.latitude,
+                longitude: location.longitude,
+                metro_code: location.metro_code,
+                time_zone: location.time_zone,
+            }),
+        postal: result
+            .postal
+            .as_ref()
+            .map(|postal| geoip2::city::Postal { code: postal.code }),
+        registered_country: result.registered_country.as_ref().map(country),
+        represented_country: result.represented_country.clone(),
+        subdivisions: result.subdivisions.as_ref().map(|subdivisions| {
+            subdivisions
+                .iter()
+                .map(|subdivision| geoip2::city::Subdivision {
+                    geoname_id: subdivision.geoname_id,
+                    iso_code: subdivision.iso_code,
+                    names: subdivision.names.clone(),
+                })
+                .collect()
+        }),
+        traits: result.traits.as_ref().map(|traits| geoip2::city::Traits {
+            is_anonymous_proxy: traits.is_anonymous_proxy,
+            is_anycast: traits.is_anycast,
+            is_satellite_provider: traits.is_satellite_provider,
+        }),
+    }
+}
+
+/// Return the ASN, City and confidence results for an IP address from its
+/// GeoIP2-Enterprise record, decoded once
+fn lookup_enterprise_record(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<(LookupAsnResult, LookupCityResult, LookupConfidence), GeoError> {
+    let enterprise_reader = match &database.enterprise_reader {
+        Some(enterprise_reader) => enterprise_reader,
+        None => return Err(GeoError::NotConfigured(String::from("Enterprise"))),
+    };
+
+    // The Enterprise record is a superset of the City record
+    // http://oschwald.github.io/maxminddb-rust/maxminddb/geoip2/struct.Enterprise.html
+    let (result, prefix_len): (geoip2::Enterprise, usize) =
+        enterprise_reader.lookup_prefix(addr).map_err(|error| {
+            if debug {
+                println!("lookup_enterprise(addr: {addr:#?}) error: {error:#?}");
+            }
+            if verbose {
+                //TODO:
+            }
+            GeoError::from_lookup(addr, error)
+        })?;
+    let languages = &enterprise_reader.metadata.languages;
+    let city = get_city_result(
+        addr,
+        get_enterprise_city(&result),
+        prefix_len,
+        locales,
+        languages,
+    );
+
+    // The ASN is one of the traits of the Enterprise record, found in the same network
+    let traits = result.traits.as_ref();
+    let asn = LookupAsnResult {
+        asn: traits
+            .and_then(|traits| traits.autonomous_system_number)
+            .unwrap_or(0),
+        asn_organization: traits
+            .and_then(|traits| traits.autonomous_system_organization)
+            .unwrap_or("-")
+            .to_string(),
+        network: city.network.clone(),
+        prefix_len: city.prefix_len,
+    };
+
+    Ok((asn, city, get_confidence(result)))
+}
+
+/// Return a LookupResult structure for an IP address from the GeoIP2-Enterprise database
+///
+/// The Enterprise record holds the City record, the ASN and the confidence of
+/// the location, the other sections are left empty. The IPv4 address embedded
+/// in an IPv4-mapped, NAT64, 6to4 or Teredo address is looked up.
+pub fn lookup_enterprise(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupResult, GeoError> {
+    let original = addr;
+    let addr = effective_address(addr);
+    let (asn, city, confidence) =
+        match lookup_enterprise_record(database, addr, locales, debug, verbose) {
+            Ok(result) => result,
+            Err(GeoError::AddressNotFound(_)) => return Err(GeoError::AddressNotFound(original)),
+            Err(error) => return Err(error),
+        };
+
+    Ok(LookupResult {
+        confidence: Some(confidence),
+        ..LookupResult::new(original, asn, city)
+    })
+}
+
+/// Return the value at a dot separated path or JSON Pointer in a record
+///
+/// e.g. "subdivisions.0.iso_code" -> "/subdivisions/0/iso_code", `Value::Null` when missing.
+/// The segments of a dot separated path are escaped, so keys may contain "~" or "/".
+fn get_field(record: &Value, path: &str) -> Value {
+    // https://docs.rs/serde_json/latest/serde_json/enum.Value.html#method.pointer
+    let pointer = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        // https://www.rfc-editor.org/rfc/rfc6901#section-3
+        path.split('.')
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    };
+    record.pointer(&pointer).cloned().unwrap_or(Value::Null)
+}
+
+/// Return the fields projected from each dataset for an IP address
+///
+/// Fields missing from a record, or all fields when the dataset has no record
+/// for the address, are `null`.
+pub fn lookup_datasets(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    debug: bool,
+    verbose: bool,
+) -> Result<BTreeMap<String, BTreeMap<String, Value>>, GeoError> {
+    let mut results = BTreeMap::new();
+    for (name, dataset) in &database.datasets {
+        // http://oschwald.github.io/maxminddb-rust/maxminddb/struct.Reader.html#method.lookup
+        let record = match dataset.reader.lookup::<Value>(addr) {
+            Ok(record) => record,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => Value::Null,
+            Err(error) => {
+                if debug {
+                    println!("lookup_datasets(addr: {addr:#?}, name: {name}) error: {error:#?}");
+                }
+                if verbose {
+                    //TODO:
+                }
+                return Err(GeoError::from_lookup(addr, error));
+            }
+        };
+        let fields = dataset
+            .fields
+            .iter()
+            .map(|(field, path)| (field.clone(), get_field(&record, path)))
+            .collect();
+        results.insert(name.clone(), fields);
+    }
+    Ok(results)
+}
+
+/// Return the section from an optional database lookup
+///
+/// `None` when the database is not configured, the `default` values when the
+/// address has no record in it.
+fn optional_section<T>(
+    result: Result<T, GeoError>,
+    default: impl FnOnce() -> T,
+) -> Result<Option<T>, GeoError> {
+    match result {
+        Ok(section) => Ok(Some(section)),
+        Err(GeoError::AddressNotFound(_)) => Ok(Some(default())),
+        Err(GeoError::NotConfigured(_)) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Return a LookupCityResult structure for an IP address from the City database,
+/// falling back to the Country database when the City database has no record
+/// (or is not configured)
+pub fn lookup_location(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupCityResult, GeoError> {
+    match lookup_city(database, addr, locales, debug, verbose) {
+        Err(GeoError::AddressNotFound(_) | GeoError::NotConfigured(_))
+            if database.country_reader.is_some() =>
+        {
+            lookup_country(database, addr, locales, debug, verbose)
+        }
+        city => city,
+    }
+}
+
 /// Return a LookupResult structure for an IP address
+///
+/// An address found in only one of the databases uses the default values for
+/// the other, `GeoError::AddressNotFound` is returned when none has a record.
+/// Special-purpose addresses (e.g. private or loopback) without a record are
+/// returned with the default values and their `scope` instead. The IPv4 address
+/// embedded in an IPv4-mapped, NAT64, 6to4 or Teredo address is looked up.
+/// Names are returned in the first of the `locales` available (see `lookup_city`).
+/// When the GeoIP2-Enterprise database has a record for the address the location
+/// and its `confidence` are taken from it in place of the City database.
 pub fn lookup(
-    asn_database_file: &String,
-    city_database_file: &String,
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupResult, GeoError> {
+    lookup_databases(database, addr, locales, true, debug, verbose)
+}
+
+/// Return a LookupResult structure for an IP address without the GeoIP2-Enterprise
+/// database (see `lookup`), the `confidence` section is left out
+pub fn lookup_without_enterprise(
+    database: &GeoDatabase,
+    addr: IpAddr,
+    locales: &[String],
+    debug: bool,
+    verbose: bool,
+) -> Result<LookupResult, GeoError> {
+    lookup_databases(database, addr, locales, false, debug, verbose)
+}
+
+fn lookup_databases(
+    database: &GeoDatabase,
     addr: IpAddr,
+    locales: &[String],
+    enterprise: bool,
     debug: bool,
     verbose: bool,
-) -> LookupResult {
-    let asn = lookup_asn(asn_database_file, addr, debug, verbose);
-    let city = lookup_city(city_database_file, addr, debug, verbose);
-    let summary = get_summary(&asn, &city);
+) -> Result<LookupResult, GeoError> {
+    let original = addr;
+    let addr = effective_address(addr);
+    let asn = lookup_asn(database, addr, debug, verbose);
+
+    // The location and its confidence come from the Enterprise record when it has
+    // one, the confidence does not describe the records of the other databases
+    let enterprise = match enterprise {
+        true => match lookup_enterprise_record(database, addr, locales, debug, verbose) {
+            Ok((_, city, confidence)) => Some((city, confidence)),
+            Err(GeoError::AddressNotFound(_) | GeoError::NotConfigured(_)) => None,
+            Err(error) => return Err(error),
+        },
+        false => None,
+    };
+    let (city, confidence) = match enterprise {
+        Some((city, confidence)) => (Ok(city), Some(confidence)),
+        None => (
+            lookup_location(database, addr, locales, debug, verbose),
+            None,
+        ),
+    };
+    let default_city = || {
+        let chain = locale_chain(locales, database.languages());
+        LookupCityResult {
+            locale: chain[0].clone(),
+            ..LookupCityResult::default()
+        }
+    };
+    let (asn, city) = match (asn, city) {
+        (
+            Err(GeoError::AddressNotFound(_)),
+            Err(GeoError::AddressNotFound(_) | GeoError::NotConfigured(_)),
+        ) => {
+            if classify(addr).is_none() {
+                return Err(GeoError::AddressNotFound(original));
+            }
+            (LookupAsnResult::default(), default_city())
+        }
+        (Err(GeoError::AddressNotFound(_)), city) => (LookupAsnResult::default(), city?),
+        (asn, Err(GeoError::AddressNotFound(_) | GeoError::NotConfigured(_))) => {
+            (asn?, default_city())
+        }
+        (asn, city) => (asn?, city?),
+    };
+    // Sections from the commercial databases (when configured)
+    let isp = optional_section(
+        lookup_isp(database, addr, debug, verbose),
+        LookupIspResult::default,
+    )?;
+    let connection_type = optional_section(
+        lookup_connection_type(database, addr, debug, verbose),
+        || String::from("-"),
+    )?;
+    let anonymous = optional_section(
+        lookup_anonymous_ip(database, addr, debug, verbose),
+        LookupAnonymousResult::default,
+    )?;
+    let domain = optional_section(lookup_domain(database, addr, debug, verbose), || {
+        String::from("-")
+    })?;
+    let datasets = lookup_datasets(database, addr, debug, verbose)?;
+
+    Ok(LookupResult {
+        isp,
+        connection_type,
+        anonymous,
+        domain,
+        confidence,
+        datasets,
+        ..LookupResult::new(original, asn, city)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_missing_file() {
+        let result = GeoDatabase::open(
+            &String::from("does-not-exist.mmdb"),
+            &String::from("GeoLite2-City.mmdb"),
+        );
+        assert!(matches!(result, Err(GeoError::MissingFile { .. })));
+    }
+
+    #[test]
+    fn test_open_wrong_database_type() {
+        // The ASN and City database files are swapped
+        let result = GeoDatabase::open(
+            &String::from("GeoLite2-City.mmdb"),
+            &String::from("GeoLite2-ASN.mmdb"),
+        );
+        assert!(matches!(result, Err(GeoError::WrongDatabaseType { .. })));
+    }
+
+    #[test]
+    fn test_open_files_wrong_country_database_type() {
+        let result = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            country: Some(String::from("GeoLite2-City.mmdb")),
+            ..DatabaseFiles::default()
+        });
+        assert!(matches!(result, Err(GeoError::WrongDatabaseType { .. })));
+
+        // The GeoLite2-ASN database is not the GeoIP2-ISP database
+        let result = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            isp: Some(String::from("GeoLite2-ASN.mmdb")),
+            ..DatabaseFiles::default()
+        });
+        assert!(matches!(result, Err(GeoError::WrongDatabaseType { .. })));
+    }
+
+    #[test]
+    fn test_get_summary_registered_country() {
+        let asn = LookupAsnResult {
+            asn: 64496,
+            asn_organization: String::from("EXAMPLE"),
+            ..LookupAsnResult::default()
+        };
+        let mut city = LookupCityResult {
+            city: String::from("London"),
+            country: (String::from("GB"), String::from("United Kingdom")),
+            subdivisions: vec![LookupSubdivision {
+                iso_code: String::from("ENG"),
+                name: String::from("England"),
+                full_iso_code: String::from("GB-ENG"),
+            }],
+            registered_country: (String::from("GB"), String::from("United Kingdom")),
+            ..LookupCityResult::default()
+        };
+        assert_eq!(get_summary(&asn, &city), "London,ENG/GB; EXAMPLE (64496);");
 
-    LookupResult {
-        address: addr,
-        asn: asn.asn,
-        asn_organization: asn.asn_organization,
-        city: city.city,
-        continent: city.continent,
-        country: city.country,
-        subdivisions: city.subdivisions,
-        summary,
+        // A network registered to another country
+        city.registered_country = (String::from("DE"), String::from("Germany"));
+        assert_eq!(
+            get_summary(&asn, &city),
+            "London,ENG/GB (registered: DE); EXAMPLE (64496);"
+        );
+    }
+
+    #[test]
+    fn test_get_summary_most_specific_subdivision() {
+        let asn = LookupAsnResult {
+            asn: 64496,
+            asn_organization: String::from("EXAMPLE"),
+            ..LookupAsnResult::default()
+        };
+        let subdivision = |iso_code: &str, name: &str| LookupSubdivision {
+            iso_code: iso_code.to_string(),
+            name: name.to_string(),
+            full_iso_code: format!("ES-{iso_code}"),
+        };
+        let mut city = LookupCityResult {
+            city: String::from("Madrid"),
+            country: (String::from("ES"), String::from("Spain")),
+            ..LookupCityResult::default()
+        };
+        assert_eq!(get_summary(&asn, &city), "Madrid,-/ES; EXAMPLE (64496);");
+
+        // Community of Madrid -> Province of Madrid
+        city.subdivisions = vec![subdivision("MD", "Madrid"), subdivision("M", "Madrid")];
+        assert_eq!(get_summary(&asn, &city), "Madrid,M/ES; EXAMPLE (64496);");
+    }
+
+    #[test]
+    fn test_get_full_iso_code() {
+        assert_eq!(get_full_iso_code("GB", "ENG"), "GB-ENG");
+        // A subdivision without a country, or without a code of its own
+        assert_eq!(get_full_iso_code("-", "ENG"), "ENG");
+        assert_eq!(get_full_iso_code("GB", "-"), "-");
+        assert_eq!(get_full_iso_code("-", "-"), "-");
+    }
+
+    #[test]
+    fn test_get_network() {
+        let addr = "4.3.2.1".parse::<IpAddr>().unwrap();
+        assert_eq!(get_network(addr, 9), (String::from("4.0.0.0/9"), Some(9)));
+        let addr = "2600::1".parse::<IpAddr>().unwrap();
+        assert_eq!(get_network(addr, 12), (String::from("2600::/12"), Some(12)));
+        // A prefix length longer than the address is not a network
+        assert_eq!(get_network(addr, 129), (String::from("-"), None));
+    }
+
+    #[test]
+    fn test_get_field() {
+        let record = serde_json::json!({
+            "city": {"names": {"en": "London"}},
+            "subdivisions": [{"iso_code": "ENG"}],
+        });
+        assert_eq!(get_field(&record, "city.names.en"), "London");
+        assert_eq!(get_field(&record, "/city/names/en"), "London");
+        assert_eq!(get_field(&record, "subdivisions.0.iso_code"), "ENG");
+        assert_eq!(get_field(&record, "postal.code"), Value::Null);
+        assert_eq!(get_field(&Value::Null, "city.names.en"), Value::Null);
+
+        // Keys containing "~" or "/" are escaped
+        let record = serde_json::json!({"ranges": {"a/b": {"~x": 1}}});
+        assert_eq!(get_field(&record, "ranges.a/b.~x"), 1);
+        assert_eq!(get_field(&record, "/ranges/a~1b/~0x"), 1);
+    }
+
+    #[test]
+    fn test_locale_chain() {
+        let available: Vec<String> = ["de", "en", "es", "fr", "ja", "pt-BR", "ru", "zh-CN"]
+            .iter()
+            .map(|locale| locale.to_string())
+            .collect();
+        let chain = |requested: &[&str]| {
+            let requested: Vec<String> = requested.iter().map(|l| l.to_string()).collect();
+            locale_chain(&requested, &available)
+        };
+        assert_eq!(chain(&[]), vec!["en"]);
+        assert_eq!(chain(&["de-AT", "fr"]), vec!["de", "fr", "en"]);
+        assert_eq!(chain(&["pt", "zh-cn"]), vec!["pt-BR", "zh-CN", "en"]);
+        assert_eq!(chain(&["xx", "en", "ru"]), vec!["en", "ru"]);
+        // Different regions of a language are not matched
+        assert_eq!(chain(&["zh-TW"]), vec!["en"]);
+        assert_eq!(chain(&["pt-PT", "fr"]), vec!["fr", "en"]);
+    }
+
+    #[test]
+    fn test_locale_names_fallback() {
+        let chain = vec![String::from("de"), String::from("en")];
+        let mut names = LocaleNames::new(&chain);
+        assert_eq!(names.locale(), "de");
+        let german = BTreeMap::from([("de", "München"), ("en", "Munich")]);
+        assert_eq!(names.get(Some(&german)), "München");
+        assert_eq!(names.get(None), "-");
+        assert!(!names.fallback);
+
+        // A record without the default locale uses any name available
+        let japanese = BTreeMap::from([("ja", "ミュンヘン")]);
+        assert_eq!(names.get(Some(&japanese)), "ミュンヘン");
+        assert!(names.fallback);
+        assert_eq!(names.get(Some(&BTreeMap::new())), "-");
+        // The most preferred locale a name was found in is reported
+        assert_eq!(names.locale(), "de");
+
+        // The locale of names only found outside the chain is reported
+        let mut names = LocaleNames::new(&chain);
+        assert_eq!(names.get(Some(&japanese)), "ミュンヘン");
+        assert_eq!(names.locale(), "ja");
+        let english = BTreeMap::from([("en", "Munich")]);
+        assert_eq!(names.get(Some(&english)), "Munich");
+        assert_eq!(names.locale(), "en");
+        assert!(names.fallback);
+    }
+
+    #[test]
+    fn test_lookup_country_only() {
+        // A deployment with only the ASN and Country databases
+        let database = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            country: Some(String::from("GeoLite2-Country.mmdb")),
+            ..DatabaseFiles::default()
+        })
+        .unwrap();
+        let addr = "8.8.8.8".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert_eq!(result.country.0, String::from("US"));
+        assert_eq!(result.continent.0, String::from("NA"));
+        assert_eq!(result.city, String::from("-"));
+        assert!(result.subdivisions.is_empty());
+        assert!(result.location.latitude.is_none());
+
+        // The City database is not configured
+        let result = lookup_city(&database, addr, &[], false, false);
+        assert_eq!(
+            result.unwrap_err(),
+            GeoError::NotConfigured(String::from("City"))
+        );
+    }
+
+    #[test]
+    fn test_lookup_traits() {
+        // The MaxMind test databases (see `make test-data`)
+        let database = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            city: Some(String::from("test-data/GeoIP2-City-Test.mmdb")),
+            country: Some(String::from("test-data/GeoIP2-Country-Test.mmdb")),
+            ..DatabaseFiles::default()
+        })
+        .unwrap();
+        let traits = |address: &str, country: bool| {
+            let addr = address.parse::<IpAddr>().unwrap();
+            let result = match country {
+                true => lookup_country(&database, addr, &[], false, false),
+                false => lookup_city(&database, addr, &[], false, false),
+            }
+            .unwrap();
+            (
+                result.is_anycast,
+                result.is_anonymous_proxy,
+                result.is_satellite_provider,
+            )
+        };
+        assert_eq!(traits("214.1.1.0", false), (true, false, false));
+        assert_eq!(traits("67.43.156.0", true), (false, true, false));
+        assert_eq!(traits("202.196.224.0", true), (false, false, true));
+    }
+
+    #[test]
+    fn test_lookup_commercial_sections() {
+        // The MaxMind test databases (see `make test-data`)
+        let database = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            city: Some(String::from("test-data/GeoIP2-City-Test.mmdb")),
+            isp: Some(String::from("test-data/GeoIP2-ISP-Test.mmdb")),
+            connection_type: Some(String::from("test-data/GeoIP2-Connection-Type-Test.mmdb")),
+            anonymous_ip: Some(String::from("test-data/GeoIP2-Anonymous-IP-Test.mmdb")),
+            domain: Some(String::from("test-data/GeoIP2-Domain-Test.mmdb")),
+            enterprise: Some(String::from("test-data/GeoIP2-Enterprise-Test.mmdb")),
+            ..DatabaseFiles::default()
+        })
+        .unwrap();
+        let addr = |address: &str| address.parse::<IpAddr>().unwrap();
+
+        let isp = lookup_isp(&database, addr("1.128.0.0"), false, false).unwrap();
+        assert_eq!(isp.asn, 1221);
+        assert_eq!(isp.asn_organization, "Telstra Pty Ltd");
+        assert_eq!(isp.isp, "Telstra Internet");
+        assert_eq!(isp.organization, "Telstra Internet");
+        assert_eq!(isp.mobile_country_code, "-");
+        let isp = lookup_isp(&database, addr("149.101.100.0"), false, false).unwrap();
+        assert_eq!(isp.mobile_country_code, "310");
+        assert_eq!(isp.mobile_network_code, "004");
+
+        let connection_type =
+            |address| lookup_connection_type(&database, addr(address), false, false);
+        assert_eq!(connection_type("1.0.0.0").unwrap(), "Cable/DSL");
+        assert_eq!(connection_type("1.0.1.0").unwrap(), "Cellular");
+
+        let anonymous = lookup_anonymous_ip(&database, addr("1.2.0.0"), false, false).unwrap();
+        assert!(anonymous.is_anonymous);
+        assert!(anonymous.is_vpn);
+        assert!(!anonymous.is_tor);
+
+        let domain = lookup_domain(&database, addr("1.2.0.0"), false, false).unwrap();
+        assert_eq!(domain, "maxmind.com");
+
+        let confidence = lookup_confidence(&database, addr("74.209.24.0"), false, false).unwrap();
+        assert_eq!(confidence.city, Some(11));
+        assert_eq!(confidence.country, Some(99));
+        assert_eq!(confidence.postal, Some(11));
+        assert_eq!(confidence.subdivisions, vec![Some(93)]);
+
+        // The confidence is only returned with the location of the Enterprise record
+        let result = lookup(&database, addr("74.209.24.0"), &[], false, false).unwrap();
+        assert_eq!(result.city, "Chatham");
+        assert_eq!(result.confidence.unwrap().city, Some(11));
+        let result = lookup(&database, addr("8.8.8.8"), &[], false, false).unwrap();
+        assert_eq!(result.asn, 15169);
+        assert!(result.confidence.is_none());
+    }
+
+    #[test]
+    fn test_lookup_special_purpose_address() {
+        let database = GeoDatabase::open(
+            &String::from("GeoLite2-ASN.mmdb"),
+            &String::from("GeoLite2-City.mmdb"),
+        )
+        .unwrap();
+        let addr = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert_eq!(result.scope, "loopback");
+        assert!(result.reserved);
+        assert_eq!(result.asn, 0);
+        assert_eq!(result.country, (String::from("-"), String::from("-")));
+        assert_eq!(result.summary, "-,-/-; - (0);");
+
+        // Global addresses are not reserved
+        let addr = "8.8.8.8".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert_eq!(result.scope, "global");
+        assert!(!result.reserved);
+
+        // Nor are globally reachable special-purpose addresses
+        let addr = "192.0.0.9".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert_eq!(result.scope, "anycast");
+        assert!(!result.reserved);
+    }
+
+    #[test]
+    fn test_lookup_embedded_ipv4() {
+        let database = GeoDatabase::open(
+            &String::from("GeoLite2-ASN.mmdb"),
+            &String::from("GeoLite2-City.mmdb"),
+        )
+        .unwrap();
+        for (address, encoding) in [
+            ("::ffff:8.8.8.8", "ipv4-mapped"),
+            ("64:ff9b::808:808", "nat64"),
+            ("2002:808:808::1", "6to4"),
+            ("2001:0:4136:e378:8000:63bf:f7f7:f7f7", "teredo"),
+        ] {
+            let addr = address.parse::<IpAddr>().unwrap();
+            let result = lookup(&database, addr, &[], false, false).unwrap();
+            assert_eq!(result.address, addr);
+            assert_eq!(result.effective_address.to_string(), "8.8.8.8");
+            assert_eq!(result.address_encoding, Some(encoding.to_string()));
+            assert_eq!(result.asn, 15169);
+            assert_eq!(result.country.0, String::from("US"));
+        }
+    }
+
+    #[test]
+    fn test_lookup_datasets() {
+        // Any .mmdb can be registered as a dataset, here the City database
+        let fields = [("country", "country.iso_code"), ("city", "city.names.en")]
+            .iter()
+            .map(|(field, path)| (field.to_string(), path.to_string()))
+            .collect();
+        let database = GeoDatabase::open_files(&DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            datasets: BTreeMap::from([(
+                String::from("example"),
+                DatasetConfig {
+                    file: String::from("GeoLite2-City.mmdb"),
+                    fields,
+                    ..DatasetConfig::default()
+                },
+            )]),
+            ..DatabaseFiles::default()
+        })
+        .unwrap();
+        let addr = "8.8.8.8".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert_eq!(result.datasets["example"]["country"], "US");
+        assert_eq!(result.datasets["example"]["city"], Value::Null);
+    }
+
+    #[test]
+    fn test_load_datasets_invalid_config() {
+        let result = load_datasets("does-not-exist.json");
+        assert!(matches!(result, Err(GeoError::InvalidConfig { .. })));
+    }
+
+    #[test]
+    fn test_lookup_commercial_sections_omitted() {
+        let database = GeoDatabase::open(
+            &String::from("GeoLite2-ASN.mmdb"),
+            &String::from("GeoLite2-City.mmdb"),
+        )
+        .unwrap();
+        let addr = "8.8.8.8".parse::<IpAddr>().unwrap();
+        let result = lookup(&database, addr, &[], false, false).unwrap();
+        assert!(result.isp.is_none());
+        assert!(result.anonymous.is_none());
+
+        // The sections are left out of the JSON when not configured
+        let result = serde_json::to_value(&result).unwrap();
+        for section in [
+            "isp",
+            "connection_type",
+            "anonymous",
+            "domain",
+            "confidence",
+            "datasets",
+        ] {
+            assert!(result.get(section).is_none(), "{section}");
+        }
+        assert_eq!(
+            lookup_domain(&database, addr, false, false).unwrap_err(),
+            GeoError::NotConfigured(String::from("Domain"))
+        );
     }
 }
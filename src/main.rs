@@ -1,15 +1,27 @@
 use std::net::IpAddr;
+use std::sync::Arc;
 
 // A web framework for Rust
 // https://docs.rs/actix-web/latest/actix_web/web/index.html
 // cargo add actix-web
-use actix_web::{dev::ConnectionInfo, get, middleware::Logger, web, App, HttpResponse, HttpServer};
+use actix_web::{
+    dev::ConnectionInfo,
+    get,
+    http::header::{AcceptLanguage, Header},
+    middleware::Logger,
+    post, web, App, HttpRequest, HttpResponse, HttpServer,
+};
 
 // A Prometheus instrumentation middleware for use with actix-web
 // https://docs.rs/actix-web-prom/latest/actix_web_prom/
 // cargo add actix-web-prom
 use actix_web_prom::PrometheusMetricsBuilder;
 
+// Asynchronous streams
+// https://docs.rs/futures-util/latest/futures_util/
+// cargo add futures-util --no-default-features
+use futures_util::{stream, StreamExt};
+
 // Timezone-aware date and time
 // https://docs.rs/chrono/latest/chrono/
 // cargo add chrono
@@ -41,11 +53,34 @@ use serde_json::json;
 // Utilities for implementing and composing tracing subscribers
 // https://docs.rs/tracing-subscriber/latest/tracing_subscriber
 // cargo add tracing-subscriber
-use tracing::{debug, info, Level};
+use tracing::{debug, error, info, Level};
 use tracing_subscriber::FmtSubscriber;
 
 // IP information lookup
-use actix_geo_widget::{lookup, lookup_metadata};
+use actix_geo_widget::{
+    load_datasets, lookup_metadata, parse_address, DatabaseFiles, GeoDatabase, GeoError,
+    GeoProvider, LookupResult, MaxMindEditions, MaxMindProvider, OverrideProvider, ProviderChain,
+    SharedGeoDatabase,
+};
+
+// Batch and streamed lookups
+mod bulk;
+
+// Field selection
+mod fields;
+use fields::FieldSelection;
+
+// Error responses
+mod problem;
+use problem::ApiError;
+
+// Response formats
+mod render;
+use render::{client_format, render_response, request_format, Format, VARY_ACCEPT, VARY_CLIENT};
+
+// Database hot reload
+mod reload;
+use reload::{DatabaseReloader, ReloadMetrics};
 
 /// RequestPath structure
 #[derive(Debug, Deserialize)]
@@ -57,94 +92,349 @@ struct RequestPath {
 #[derive(Debug, Deserialize)]
 struct RequestQuery {
     compact: Option<String>,
+    // Comma separated list of locales for names, e.g. "de,en"
+    lang: Option<String>,
+    // Comma separated list of fields to return, e.g. "country,asn,location.latitude"
+    fields: Option<String>,
+    // Format of the response, e.g. "yaml", in place of the `Accept` header
+    format: Option<String>,
+}
+
+/// Return the locales requested for names, most preferred first
+///
+/// The `lang` query parameter takes precedence over the `Accept-Language` header.
+fn request_locales(req: &HttpRequest, query: &RequestQuery) -> Vec<String> {
+    if let Some(lang) = &query.lang {
+        return lang
+            .split(',')
+            .map(|locale| locale.trim().to_string())
+            .filter(|locale| !locale.is_empty())
+            .collect();
+    }
+
+    // https://docs.rs/actix-web/latest/actix_web/http/header/struct.AcceptLanguage.html
+    match AcceptLanguage::parse(req) {
+        Ok(accept_language) => accept_language
+            .ranked()
+            .iter()
+            .filter_map(|preference| preference.item())
+            .map(|locale| locale.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Return the fields of a LookupResult requested with the `fields` query parameter
+fn request_fields(query: &RequestQuery) -> Result<Option<FieldSelection>, ApiError> {
+    query
+        .fields
+        .as_deref()
+        .map(FieldSelection::parse)
+        .transpose()
 }
 
 /// Return a LookupResult in JSON format for an IP address
 #[get("/address/{address}")]
 async fn specific_address(
+    req: HttpRequest,
     data: web::Data<AppData>,
     path: web::Path<RequestPath>,
     query: web::Query<RequestQuery>,
-) -> HttpResponse {
-    // Convert the address String into an IpAddr
-    // TODO: Conversion error handling -> 400 Client Error
-    let address = path.address.parse::<IpAddr>().unwrap();
-
-    // Lookup the information for the IP address
-    let asn_database_file = &data.asn_database_file;
-    let city_database_file = &data.city_database_file;
-    let result = lookup(
-        asn_database_file,  // --asn-database-file
-        city_database_file, // --city-database-file
-        address,
-        data.debug,   // --debug
-        data.verbose, // --verbose
-    );
+) -> Result<HttpResponse, ApiError> {
+    // Convert the address String into an IpAddr, the canonical address is returned in the result
+    // e.g. "[2001:DB8::1]:443" -> "2001:db8::1", "134744072" -> "8.8.8.8"
+    let address = parse_address(&path.address)
+        .ok_or_else(|| ApiError::InvalidAddress(path.address.clone()))?;
+
+    let fields = request_fields(&query)?;
+    let format = request_format(&req, query.format.as_deref())?;
+    lookup_response(&req, &data, &query, address, fields, format, VARY_ACCEPT)
+}
 
-    // Format the result into JSON
+/// Return the response of a lookup of an IP address, rendered in `format`
+///
+/// The result is limited to the `fields` requested and the locale of its names
+/// returned in the `Content-Language` header.
+fn lookup_response(
+    req: &HttpRequest,
+    data: &AppData,
+    query: &RequestQuery,
+    address: IpAddr,
+    fields: Option<FieldSelection>,
+    format: Format,
+    vary: &'static str,
+) -> Result<HttpResponse, ApiError> {
+    // Lookup the information for the IP address using the provider
+    let result = data
+        .provider
+        .lookup(address, &request_locales(req, query))?;
+    let content_language = result.locale.clone();
+
+    // Format the result into JSON, limited to the fields requested
     // https://docs.rs/serde_json/latest/serde_json/macro.json.html
-    let result_as_json = json!(result);
+    let mut result_as_json = json!(result);
+    if let Some(fields) = fields {
+        result_as_json = fields.apply(&result_as_json);
+    }
 
-    // If the request's query string contains "compact", return the result as compact JSON
+    // Render the result in the format requested, compact JSON when the query string contains "compact"
     // https://docs.rs/actix-web/latest/actix_web/web/struct.Query.html
-    if query.compact.is_some() {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string(&result_as_json).unwrap())
-    } else {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string_pretty(&result_as_json).unwrap())
-    }
+    let (mut response, body) =
+        render_response(format, &result_as_json, query.compact.is_some(), vary)?;
+    Ok(response
+        .insert_header(("Content-Language", content_language))
+        .body(body))
 }
 
-/// Return a LookupResult in JSON format for the requesting client's IP address
-#[get("/address")]
-async fn client_address(
-    conn: ConnectionInfo,
-    data: web::Data<AppData>,
-    query: web::Query<RequestQuery>,
-) -> HttpResponse {
+/// Return the requesting client's IP address
+fn client_ip(conn: &ConnectionInfo) -> Result<IpAddr, ApiError> {
     // Get the client's "real" IP address (which may be spoofed)
     // https://github.com/actix/actix-web/blob/master/actix-web/src/info.rs#L158
     // The address is resolved through the following, in order:
     // - `Forwarded` header
     // - `X-Forwarded-For` header
     // - peer address of opened socket (same as [`remote_addr`](Self::remote_addr))
-    let realip_remote_addr = conn.realip_remote_addr().unwrap().to_string();
+    let realip_remote_addr = conn.realip_remote_addr().unwrap_or_default().to_string();
 
     // Convert the address String into an IpAddr
-    // TODO: Conversion error handling -> 400 Client Error
-    let address = realip_remote_addr.parse::<IpAddr>().unwrap();
-
-    // Lookup the information for the IP address
-    let asn_database_file = &data.asn_database_file;
-    let city_database_file = &data.city_database_file;
-    let result = lookup(
-        asn_database_file,  // --asn-database-file
-        city_database_file, // --city-database-file
-        address,
-        data.debug,   // --debug
-        data.verbose, // --verbose
+    parse_address(&realip_remote_addr).ok_or(ApiError::InvalidAddress(realip_remote_addr))
+}
+
+/// Return a LookupResult in JSON format for the requesting client's IP address
+///
+/// Command line tools (curl, wget, HTTPie) are answered with a line of plain
+/// text, "<ADDRESS> <SUMMARY>", unless they ask for a format.
+#[get("/address")]
+async fn client_address(
+    req: HttpRequest,
+    conn: ConnectionInfo,
+    data: web::Data<AppData>,
+    query: web::Query<RequestQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let address = client_ip(&conn)?;
+    let fields = request_fields(&query)?;
+    let format = client_format(&req, query.format.as_deref())?;
+    lookup_response(&req, &data, &query, address, fields, format, VARY_CLIENT)
+}
+
+/// Return the requesting client's IP address as plain text
+#[get("/ip")]
+async fn ip(conn: ConnectionInfo) -> Result<HttpResponse, ApiError> {
+    let address = client_ip(&conn)?;
+
+    // Return the bare address with a trailing newline
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+        .body(format!("{address}\n")))
+}
+
+/// Return a list of LookupResults in JSON format for a JSON list of IP addresses
+///
+/// Results are in the order of the addresses, an address which could not be
+/// looked up is returned as `{"address": ..., "error": <problem details>}`.
+#[post("/address")]
+async fn batch_address(
+    req: HttpRequest,
+    data: web::Data<AppData>,
+    query: web::Query<RequestQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    // Parse the request body as a JSON list of addresses
+    let inputs: Vec<String> =
+        serde_json::from_slice(&body).map_err(|err| ApiError::InvalidBody(err.to_string()))?;
+    if inputs.len() > data.max_batch_size {
+        return Err(ApiError::BatchTooLarge {
+            size: inputs.len(),
+            max: data.max_batch_size,
+        });
+    }
+
+    // Convert the address Strings into IpAddrs
+    let inputs = inputs
+        .into_iter()
+        .map(|input| {
+            let address = parse_address(&input).ok_or(ApiError::InvalidAddress(input.clone()));
+            (Some(input), address)
+        })
+        .collect();
+
+    // Lookup the information for the IP addresses in one batch using the provider
+    let fields = request_fields(&query)?;
+    let format = request_format(&req, query.format.as_deref())?;
+    let items = bulk::lookup_items(
+        data.provider.as_ref(),
+        inputs,
+        &request_locales(&req, &query),
+        fields.as_ref(),
     );
 
-    // Format the result into JSON
+    // Format the results into JSON, in the order of the addresses
     // https://docs.rs/serde_json/latest/serde_json/macro.json.html
-    let result_as_json = json!(result);
+    let result_as_json = json!(items);
 
-    // If the request's query string contains "compact", return the result as compact JSON
+    // Render the results in the format requested, compact JSON when the query string contains "compact"
     // https://docs.rs/actix-web/latest/actix_web/web/struct.Query.html
-    if query.compact.is_some() {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string(&result_as_json).unwrap())
-    } else {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string_pretty(&result_as_json).unwrap())
+    let (mut response, body) = render_response(
+        format,
+        &result_as_json,
+        query.compact.is_some(),
+        VARY_ACCEPT,
+    )?;
+    Ok(response.body(body))
+}
+
+/// Stream LookupResults in NDJSON format for a streamed body of IP addresses
+///
+/// Each line of the request body is an address or a JSON object with an `ip`
+/// field, a line of results is sent back as each chunk of the body is read.
+/// The next chunk is only read once the previous results have been sent, so a
+/// slow client holds back the upload (backpressure).
+#[post("/address/stream")]
+async fn stream_address(
+    req: HttpRequest,
+    data: web::Data<AppData>,
+    query: web::Query<RequestQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    let locales = request_locales(&req, &query);
+    let fields = Arc::new(request_fields(&query)?);
+    let provider = Arc::clone(&data.provider);
+
+    // https://docs.rs/futures-util/latest/futures_util/stream/fn.unfold.html
+    let state = (payload, bulk::LineSplitter::default(), false);
+    let body = stream::unfold(state, move |(mut payload, mut lines, done)| {
+        let provider = Arc::clone(&provider);
+        let fields = Arc::clone(&fields);
+        let locales = locales.clone();
+        async move {
+            if done {
+                return None;
+            }
+
+            // Read the next chunk of the request body into complete lines
+            let (lines_read, done) = match payload.next().await {
+                Some(Ok(chunk)) => (lines.push(&chunk), false),
+                Some(Err(err)) => {
+                    return Some((Err(actix_web::Error::from(err)), (payload, lines, true)))
+                }
+                None => (lines.finish().into_iter().collect::<Vec<_>>(), true),
+            };
+
+            // Lookup the information for the lines in one batch, one JSON result per line
+            let inputs = lines_read
+                .into_iter()
+                .map(|line| match line {
+                    Ok(line) => {
+                        let address = bulk::parse_line(&line);
+                        (Some(line), address)
+                    }
+                    // The line could not be read, only the error is returned
+                    Err(err) => (None, Err(err)),
+                })
+                .collect();
+            let mut body = String::new();
+            let items = bulk::lookup_items(
+                provider.as_ref(),
+                inputs,
+                &locales,
+                fields.as_ref().as_ref(),
+            );
+            for item in items {
+                let item = serde_json::to_string(&item).unwrap_or_else(|err| {
+                    let err = ApiError::RenderFailed(err.to_string());
+                    bulk::error_item(None, &err).to_string()
+                });
+                body.push_str(&item);
+                body.push('\n');
+            }
+            Some((Ok(web::Bytes::from(body)), (payload, lines, done)))
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "application/x-ndjson"))
+        .streaming(body))
+}
+
+/// RequestFieldPath structure
+#[derive(Debug, Deserialize)]
+struct RequestFieldPath {
+    address: Option<String>,
+    field: String,
+}
+
+/// Return a field of a LookupResult as plain text, the value as in the JSON result
+///
+/// - "country" the ISO code of the country, e.g. "US"
+/// - "asn" the autonomous system number, e.g. "15169"
+/// - "city" the name of the city
+/// - "summary" the summary, e.g. "-,-/US; GOOGLE (15169);"
+///
+/// The routes only match these fields.
+fn text_field(result: &LookupResult, field: &str) -> String {
+    match field {
+        "country" => result.country.0.clone(),
+        "asn" => result.asn.to_string(),
+        "city" => result.city.clone(),
+        "summary" => result.summary.clone(),
+        _ => unreachable!("field {field:?} is not matched by the routes"),
     }
 }
 
+/// Return a field of the LookupResult for the requesting client's IP address as plain text
+///
+/// Registered before `specific_address` so "/address/country" is the client's country.
+#[get("/address/{field:country|asn|city|summary}")]
+async fn client_address_field(
+    req: HttpRequest,
+    conn: ConnectionInfo,
+    data: web::Data<AppData>,
+    path: web::Path<RequestFieldPath>,
+    query: web::Query<RequestQuery>,
+) -> Result<HttpResponse, ApiError> {
+    field_response(&req, &conn, &data, &path, &query)
+}
+
+/// Return a field of the LookupResult for an IP address as plain text
+#[get("/address/{address}/{field:country|asn|city|summary}")]
+async fn specific_address_field(
+    req: HttpRequest,
+    conn: ConnectionInfo,
+    data: web::Data<AppData>,
+    path: web::Path<RequestFieldPath>,
+    query: web::Query<RequestQuery>,
+) -> Result<HttpResponse, ApiError> {
+    field_response(&req, &conn, &data, &path, &query)
+}
+
+/// Return the plain text response of a field for the address of the path (or the client's)
+fn field_response(
+    req: &HttpRequest,
+    conn: &ConnectionInfo,
+    data: &AppData,
+    path: &RequestFieldPath,
+    query: &RequestQuery,
+) -> Result<HttpResponse, ApiError> {
+    // The address of the path, or else the client's address
+    let address = match &path.address {
+        Some(address) => {
+            parse_address(address).ok_or_else(|| ApiError::InvalidAddress(address.clone()))?
+        }
+        None => client_ip(conn)?,
+    };
+
+    // Lookup the information for the IP address using the provider
+    let result = data
+        .provider
+        .lookup(address, &request_locales(req, query))?;
+
+    // Return the value with a trailing newline
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+        .insert_header(("Content-Language", result.locale.clone()))
+        .body(format!("{}\n", text_field(&result, &path.field))))
+}
+
 // Healthcheck response structure
 #[derive(Debug, Deserialize, Serialize)]
 struct HealthCheckResponse {
@@ -154,99 +444,46 @@ struct HealthCheckResponse {
 
 /// Health check response handler
 #[get("/healthcheck")]
-async fn healthcheck(data: web::Data<AppData>, query: web::Query<RequestQuery>) -> HttpResponse {
-    // `maximum_stale_ttl` is the maximum number of seconds a database
-    // should be used for before being replaced with an updated release.
-    let maximum_stale_ttl = (604800 * 2) + 86400; // 2 weeks + 1 day
-
-    // Lookup metadata for the ASN database
-    let asn_database_file = &data.asn_database_file;
-    let asn_metadata = lookup_metadata(
-        asn_database_file, // --asn-database-file
-    );
-
-    // Lookup metadata for the ASN database
-    let city_database_file = &data.city_database_file;
-    let city_metadata = lookup_metadata(
-        city_database_file, // --city-database-file
-    );
-
-    /*
-    Example City Metadata result
-    city_metadata: Metadata {
-        binary_format_major_version: 2,
-        binary_format_minor_version: 0,
-        build_epoch: 1722343686,
-        database_type: "GeoLite2-City",
-        description: {
-            "en": "GeoLite2City database"
-        },
-        ip_version: 6,
-        languages: ["de", "en", "es", "fr", "ja", "pt-BR", "ru", "zh-CN"],
-        node_count: 3897787,
-        record_size: 28
-    }
-    */
-
-    // Default result values
-    let mut is_healthy = true;
-    let mut reason = String::from("Check of databases passed");
+async fn healthcheck(
+    req: HttpRequest,
+    data: web::Data<AppData>,
+    query: web::Query<RequestQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let format = request_format(&req, query.format.as_deref())?;
 
-    // Check the database metadata
-    let databases = [asn_metadata, city_metadata];
-    for database in databases.iter() {
-        // The build_epoch should reflect a recent version of the database to be considered healthy
-        let build_datetime: DateTime<Utc> =
-            DateTime::from_timestamp(database.build_epoch as i64, 0).unwrap();
-        let database_age = (Utc::now() - build_datetime).num_seconds();
+    // Check the health of the provider (the databases are available and fresh)
+    let health = data.provider.health();
 
-        // Debug messages
-        if data.debug {
+    // Debug messages
+    if data.debug {
+        for database in data.provider.metadata() {
             println!(
                 "Database {} metadata: {:?}",
                 database.database_type, database,
             );
-            println!(
-                "Database {} age: {:?}",
-                database.database_type, database_age,
-            );
-        }
-
-        // Check the if the `database_age` has exceeded the `maximum_stale_ttl`
-        if database_age >= maximum_stale_ttl {
-            is_healthy = false;
-            reason = format!(
-                "Database is stale ({} build date: {})",
-                database.database_type, build_datetime,
-            );
-            break;
-        // Debug messages
-        } else if data.debug {
-            println!(
-                "Database is fresh ({} build date: {})",
-                database.database_type, build_datetime,
-            );
         }
+        println!("Provider {} health: {:?}", data.provider.name(), health);
     }
 
     // Set the result information
-    let result = HealthCheckResponse { is_healthy, reason };
+    let result = HealthCheckResponse {
+        is_healthy: health.is_healthy,
+        reason: health.reason,
+    };
 
     // Format the result into JSON
     // https://docs.rs/serde_json/latest/serde_json/macro.json.html
     let result_as_json = json!(result);
 
-    // If the request's query string contains "compact", return the result as compact JSON
+    // Render the result in the format requested, compact JSON when the query string contains "compact"
     // https://docs.rs/actix-web/latest/actix_web/web/struct.Query.html
-    if query.compact.is_some() {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string(&result_as_json).unwrap())
-    } else {
-        HttpResponse::Ok()
-            .insert_header(("Content-Type", "application/json"))
-            .body(serde_json::to_string_pretty(&result_as_json).unwrap())
-    }
+    let (mut response, body) = render_response(
+        format,
+        &result_as_json,
+        query.compact.is_some(),
+        VARY_ACCEPT,
+    )?;
+    Ok(response.body(body))
 }
 
 // Pong response structure
@@ -283,14 +520,19 @@ async fn ping(query: web::Query<RequestQuery>) -> HttpResponse {
 // Application data passed to endpoints
 struct AppData {
     debug: bool,
-    verbose: bool,
-    asn_database_file: String,
-    city_database_file: String,
+    // Lookup backend shared by all workers (the MaxMind databases by default)
+    provider: Arc<dyn GeoProvider>,
+    // Maximum number of addresses in a batch lookup
+    max_batch_size: usize,
 }
 
 // Main Actix Web service
 #[actix_web::main]
-async fn actix_main(args: Args) -> std::io::Result<()> {
+async fn actix_main(
+    args: Args,
+    database_files: DatabaseFiles,
+    overrides: Option<Arc<OverrideProvider>>,
+) -> std::io::Result<()> {
     // Initialize tracing logging using the args.<debug|verbose|...> specified
     let tracing_log_level = if args.debug {
         Level::DEBUG
@@ -322,9 +564,18 @@ async fn actix_main(args: Args) -> std::io::Result<()> {
     // Configure the log format
     //let log_format = "%a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T";
 
-    // Bring information from `args` into scope
-    let asn_database_file = args.asn_database_file;
-    let city_database_file = args.city_database_file;
+    // Open the databases once, the handle is shared by all workers
+    // Lookups respond with 503 Service Unavailable until a reload succeeds
+    let database = match GeoDatabase::open_files(&database_files) {
+        Ok(database) => Arc::new(SharedGeoDatabase::new(database)),
+        Err(err) => {
+            error!(
+                database_files = ?database_files.paths(),
+                "Unable to open databases: {err}"
+            );
+            Arc::new(SharedGeoDatabase::unavailable(err))
+        }
+    };
 
     // Prometheus middleware
     let prometheus = PrometheusMetricsBuilder::new("actix_geo_widget")
@@ -332,6 +583,30 @@ async fn actix_main(args: Args) -> std::io::Result<()> {
         .build()
         .unwrap();
 
+    // Reload the databases on SIGHUP or when the files change on disk
+    let reload_metrics = ReloadMetrics::register("actix_geo_widget", &prometheus.registry)
+        .map_err(std::io::Error::other)?;
+    let mut reloader =
+        DatabaseReloader::new(database_files, Arc::clone(&database), Some(reload_metrics));
+    if let Some(overrides) = &overrides {
+        reloader = reloader.with_overrides(Arc::clone(overrides));
+    }
+    if let Some(dataset_config_file) = &args.dataset_config_file {
+        reloader = reloader.with_dataset_config_file(dataset_config_file.clone());
+    }
+    let reloader = Arc::new(reloader);
+    #[cfg(unix)]
+    actix_web::rt::spawn(reload::reload_on_sighup(Arc::clone(&reloader)));
+    if args.reload_interval > 0 {
+        actix_web::rt::spawn(reload::reload_on_change(reloader, args.reload_interval));
+    }
+
+    // Lookups are answered from the override file and the shared (reloadable) databases
+    let provider = args.provider(&database, &overrides).map_err(|err| {
+        error!("{err}");
+        std::io::Error::other(err)
+    })?;
+
     info!("Starting actix-geo-widget");
 
     // Initialize the HTTP server with the application
@@ -341,12 +616,20 @@ async fn actix_main(args: Args) -> std::io::Result<()> {
             .wrap(prometheus.clone())
             .app_data(web::Data::new(AppData {
                 debug: args.debug,
-                verbose: args.verbose,
-                asn_database_file: asn_database_file.clone(),
-                city_database_file: city_database_file.clone(),
+                provider: Arc::clone(&provider),
+                max_batch_size: args.max_batch_size,
             }))
+            // Room for a full batch of the longest addresses
+            .app_data(web::PayloadConfig::new(
+                args.max_batch_size.saturating_mul(64).max(262_144),
+            ))
+            .service(client_address_field)
+            .service(specific_address_field)
             .service(specific_address)
             .service(client_address)
+            .service(ip)
+            .service(batch_address)
+            .service(stream_address)
             .service(healthcheck)
             .service(ping)
     })
@@ -358,7 +641,13 @@ async fn actix_main(args: Args) -> std::io::Result<()> {
 /// Print database metadata information
 fn print_database_metadata(database_file: &String, debug: bool, verbose: bool) {
     // Lookup metadata from the database file
-    let database_metadata = lookup_metadata(database_file);
+    let database_metadata = match lookup_metadata(database_file) {
+        Ok(database_metadata) => database_metadata,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
     /*
     Example City Metadata result
     city_metadata: Metadata {
@@ -416,10 +705,58 @@ struct Args {
     #[arg(long, default_value = "GeoLite2-ASN.mmdb")]
     asn_database_file: String,
 
-    /// File path to the City database
+    /// File path to the City database (an empty value disables the City database)
     #[arg(long, default_value = "GeoLite2-City.mmdb")]
     city_database_file: String,
 
+    /// File path to the Country database (used when the City database has no record)
+    #[arg(long)]
+    country_database_file: Option<String>,
+
+    /// File path to the GeoIP2-ISP database (optional)
+    #[arg(long)]
+    isp_database_file: Option<String>,
+
+    /// File path to the GeoIP2-Connection-Type database (optional)
+    #[arg(long)]
+    connection_type_database_file: Option<String>,
+
+    /// File path to the GeoIP2-Anonymous-IP database (optional)
+    #[arg(long)]
+    anonymous_ip_database_file: Option<String>,
+
+    /// File path to the GeoIP2-Domain database (optional)
+    #[arg(long)]
+    domain_database_file: Option<String>,
+
+    /// File path to the GeoIP2-Enterprise database (optional)
+    #[arg(long)]
+    enterprise_database_file: Option<String>,
+
+    /// File path to a JSON file declaring other .mmdb datasets and the fields to return
+    #[arg(long)]
+    dataset_config_file: Option<String>,
+
+    /// File path to a YAML, JSON or CSV file of networks overriding the databases
+    #[arg(long)]
+    override_file: Option<String>,
+
+    /// Order of the providers a field is taken from, e.g. "country=overrides,maxmind" (may be repeated)
+    #[arg(long, value_parser = parse_field_precedence)]
+    field_precedence: Vec<(String, Vec<String>)>,
+
+    /// Maximum number of addresses in a batch lookup (POST /address)
+    #[arg(long, default_value_t = 1000)]
+    max_batch_size: usize,
+
+    /// Seconds between checks of the database files for changes (0 to disable, SIGHUP always reloads)
+    #[arg(long, default_value_t = 60)]
+    reload_interval: u64,
+
+    /// Locale for names in offline mode, may be repeated in order of preference
+    #[arg(long, default_value = "en")]
+    lang: Vec<String>,
+
     /// Print database metadate information
     #[arg(long)]
     metadata: bool,
@@ -437,57 +774,192 @@ struct Args {
     debug: bool,
 }
 
+/// Parse a "<FIELD>=<PROVIDER>,<PROVIDER>,..." field precedence argument
+fn parse_field_precedence(value: &str) -> Result<(String, Vec<String>), String> {
+    let (field, order) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <FIELD>=<PROVIDER>,... but found {value:?}"))?;
+    let order = order
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    Ok((field.trim().to_string(), order))
+}
+
+impl Args {
+    /// Return the provider used for lookups
+    ///
+    /// The override file and the GeoIP2-Enterprise database (when configured)
+    /// are chained before the other databases, the results are merged field by
+    /// field in order of precedence. Without anything to chain the MaxMind
+    /// databases are used on their own (and results have no `sources`).
+    fn provider(
+        &self,
+        database: &Arc<SharedGeoDatabase>,
+        overrides: &Option<Arc<OverrideProvider>>,
+    ) -> Result<Arc<dyn GeoProvider>, String> {
+        let maxmind = |editions| {
+            let provider = MaxMindProvider::new(Arc::clone(database), self.debug, self.verbose);
+            Arc::new(provider.with_editions(editions)) as Arc<dyn GeoProvider>
+        };
+        if overrides.is_none()
+            && self.enterprise_database_file.is_none()
+            && self.field_precedence.is_empty()
+        {
+            return Ok(maxmind(MaxMindEditions::WithoutEnterprise));
+        }
+
+        let mut providers: Vec<Arc<dyn GeoProvider>> = Vec::new();
+        if let Some(overrides) = overrides {
+            providers.push(Arc::clone(overrides) as Arc<dyn GeoProvider>);
+        }
+        if self.enterprise_database_file.is_some() {
+            providers.push(maxmind(MaxMindEditions::Enterprise));
+        }
+        providers.push(maxmind(MaxMindEditions::WithoutEnterprise));
+        let mut chain = ProviderChain::new(providers);
+        for (field, order) in &self.field_precedence {
+            chain = chain
+                .with_precedence(field, order.clone())
+                .map_err(|err| format!("Invalid --field-precedence: {err}"))?;
+        }
+        Ok(Arc::new(chain))
+    }
+
+    /// Return the database files configured by the cli arguments
+    fn database_files(&self) -> Result<DatabaseFiles, GeoError> {
+        let datasets = match &self.dataset_config_file {
+            Some(dataset_config_file) => load_datasets(dataset_config_file)?,
+            None => Default::default(),
+        };
+        Ok(DatabaseFiles {
+            asn: self.asn_database_file.clone(),
+            city: Some(self.city_database_file.clone()).filter(|file| !file.is_empty()),
+            country: self.country_database_file.clone(),
+            isp: self.isp_database_file.clone(),
+            connection_type: self.connection_type_database_file.clone(),
+            anonymous_ip: self.anonymous_ip_database_file.clone(),
+            domain: self.domain_database_file.clone(),
+            enterprise: self.enterprise_database_file.clone(),
+            datasets,
+        })
+    }
+}
+
 // CLI configuration options using clap
 fn main() {
     let args = Args::parse();
 
+    // Collect the database files, including the datasets from the configuration file
+    let database_files = match args.database_files() {
+        Ok(database_files) => database_files,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    // Load the override file
+    let overrides = match &args.override_file {
+        Some(override_file) => match OverrideProvider::open(override_file) {
+            Ok(overrides) => Some(Arc::new(overrides)),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
     // Print database metadata information
     if args.metadata {
-        // Print ASN database metadata information
-        let asn_database_file = &args.asn_database_file;
-        print_database_metadata(asn_database_file, args.debug, args.verbose);
-
-        // Print City database metadata information
-        let city_database_file = &args.city_database_file;
-        print_database_metadata(city_database_file, args.debug, args.verbose);
+        // Print the metadata information for each configured database
+        for database_file in database_files.paths() {
+            print_database_metadata(database_file, args.debug, args.verbose);
+        }
     }
 
     // Lookup the IP address information
     if args.offline {
-        let result = lookup(
-            &args.asn_database_file,
-            &args.city_database_file,
-            args.addr.parse::<IpAddr>().unwrap(),
-            args.debug,
-            args.verbose,
-        );
-        println!("{result:?}");
+        let result = GeoDatabase::open_files(&database_files)
+            .map_err(|err| err.to_string())
+            .and_then(|database| {
+                let database = Arc::new(SharedGeoDatabase::new(database));
+                let address = parse_address(&args.addr)
+                    .ok_or_else(|| format!("Invalid IP address: {:?}", args.addr))?;
+                args.provider(&database, &overrides)?
+                    .lookup(address, &args.lang)
+                    .map_err(|err| err.to_string())
+            });
+        match result {
+            Ok(result) => println!("{result:?}"),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
     // Start the web service
     } else {
-        let _ = actix_main(args);
+        let _ = actix_main(args, database_files, overrides);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_geo_widget::LookupResult;
-    use actix_web::test;
+
+    use actix_geo_widget::{LookupAsnResult, LookupCityResult, ProviderMetadata};
+    use actix_web::{http::StatusCode, test};
+    use problem::ProblemDetails;
+
+    // Shared database handle using the databases in the working directory
+    fn test_database() -> Arc<SharedGeoDatabase> {
+        let database = GeoDatabase::open(
+            &String::from("GeoLite2-ASN.mmdb"),
+            &String::from("GeoLite2-City.mmdb"),
+        )
+        .unwrap();
+        Arc::new(SharedGeoDatabase::new(database))
+    }
+
+    // Application data using the databases in the working directory, the provider
+    // built as by the server from the cli arguments
+    fn test_app_data() -> web::Data<AppData> {
+        let args = Args::parse_from(["actix-geo-widget"]);
+        web::Data::new(AppData {
+            debug: false,
+            provider: args.provider(&test_database(), &None).unwrap(),
+            max_batch_size: 1000,
+        })
+    }
+
+    // File in the temporary directory unique to the test process, removed when dropped
+    struct TempFile(String);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let file = std::env::temp_dir()
+                .join(format!("actix-geo-widget-{}-{name}", std::process::id()));
+            TempFile(file.to_str().unwrap().to_string())
+        }
+
+        fn write(&self, contents: &str) {
+            std::fs::write(&self.0, contents).unwrap();
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
 
     #[actix_web::test]
     async fn test_client_address_forwarded() {
         // Initialize the application
-        let app = test::init_service(
-            App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
-                .service(client_address),
-        )
-        .await;
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
 
         // Send a request to the `client_address` endpoint
         let req = test::TestRequest::get()
@@ -508,17 +980,8 @@ mod tests {
     #[actix_web::test]
     async fn test_client_address_forwarded_compact() {
         // Initialize the application
-        let app = test::init_service(
-            App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
-                .service(client_address),
-        )
-        .await;
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
 
         // Send a request to the `client_address` endpoint
         let req = test::TestRequest::get()
@@ -539,17 +1002,8 @@ mod tests {
     #[actix_web::test]
     async fn test_client_address_x_forwarded_for() {
         // Initialize the application
-        let app = test::init_service(
-            App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
-                .service(client_address),
-        )
-        .await;
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
 
         // Send a request to the `client_address` endpoint
         let req = test::TestRequest::get()
@@ -567,17 +1021,40 @@ mod tests {
         );
     }
 
+    #[actix_web::test]
+    async fn test_client_address_ipv4_mapped() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
+
+        // Send a request to the `client_address` endpoint from a dual-stack load balancer
+        let req = test::TestRequest::get()
+            .uri("/address")
+            .insert_header(("X-Forwarded-For", "::ffff:4.3.2.1"))
+            .to_request();
+
+        // Send the request and parse the response as JSON
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response
+        assert_eq!(
+            result.address,
+            String::from("::ffff:4.3.2.1").parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            result.effective_address,
+            String::from("4.3.2.1").parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(result.address_encoding, Some(String::from("ipv4-mapped")));
+        assert_eq!(result.asn, 3356);
+    }
+
     #[actix_web::test]
     async fn test_specific_address_ipv4() {
         // Initialize the application
         let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
+                .app_data(test_app_data())
                 .service(specific_address),
         )
         .await;
@@ -590,11 +1067,12 @@ mod tests {
         // Send the request and parse the response as JSON
         let result: LookupResult = test::call_and_read_body_json(&app, req).await;
 
-        // Assert the response
+        // Assert the response, without anything to chain there are no sources
         assert_eq!(
             result.address,
             String::from("4.3.2.1").parse::<IpAddr>().unwrap()
         );
+        assert!(result.sources.is_empty());
     }
 
     #[actix_web::test]
@@ -602,12 +1080,7 @@ mod tests {
         // Initialize the application
         let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
+                .app_data(test_app_data())
                 .service(specific_address),
         )
         .await;
@@ -632,12 +1105,7 @@ mod tests {
         // Initialize the application
         let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
+                .app_data(test_app_data())
                 .service(specific_address),
         )
         .await;
@@ -658,45 +1126,675 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_ping() {
+    async fn test_specific_address_location() {
         // Initialize the application
         let app = test::init_service(
             App::new()
-                .app_data(web::Data::new(AppData {
-                    debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
-                }))
-                .service(ping),
+                .app_data(test_app_data())
+                .service(specific_address),
         )
         .await;
 
-        // Send a request to the `ping` endpoint
-        let req = test::TestRequest::get().uri("/ping").to_request();
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .to_request();
 
         // Send the request and parse the response as JSON
-        let result: PongResponse = test::call_and_read_body_json(&app, req).await;
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response includes the location coordinates
+        assert!(result.location.latitude.is_some());
+        assert!(result.location.longitude.is_some());
+        assert!(result.location.accuracy_radius.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_fields() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/81.2.69.160?fields=country,asn,location.latitude")
+            .to_request();
+
+        // Send the request and parse the response as JSON
+        let result: serde_json::Value = test::call_and_read_body_json(&app, req).await;
 
         // Assert the response
-        assert_eq!(result.ping, String::from("pong"));
+        let result = result.as_object().unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result["country"][0], json!("GB"));
+        assert_eq!(result["asn"], json!(20712));
+        assert_eq!(result["location"].as_object().unwrap().len(), 1);
+        assert!(result["location"]["latitude"].is_number());
     }
 
     #[actix_web::test]
-    async fn test_healthcheck() {
+    async fn test_specific_address_unknown_field() {
         // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/81.2.69.160?fields=country,colour")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("invalid_fields"));
+        assert!(result.detail.contains("colour"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_lang() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // The `lang` query parameter takes precedence over Accept-Language
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1?lang=de")
+            .insert_header(("Accept-Language", "fr"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Language").unwrap(), "de");
+        let result: LookupResult = test::read_body_json(resp).await;
+
+        // Assert the response
+        assert_eq!(result.locale, String::from("de"));
+        assert_eq!(result.country.0, String::from("US"));
+        assert_ne!(result.country.1, String::from("United States"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_accept_language() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Unsupported locales are skipped, "fr-CA" falls back to "fr"
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .insert_header(("Accept-Language", "xx, fr-CA;q=0.9, en;q=0.5"))
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response
+        assert_eq!(result.locale, String::from("fr"));
+    }
+
+    // Provider returning the same result for every address
+    struct StaticProvider;
+
+    impl GeoProvider for StaticProvider {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        fn lookup(&self, addr: IpAddr, _locales: &[String]) -> Result<LookupResult, GeoError> {
+            let asn = LookupAsnResult {
+                asn: 64496,
+                asn_organization: String::from("EXAMPLE"),
+                ..LookupAsnResult::default()
+            };
+            Ok(LookupResult::new(addr, asn, LookupCityResult::default()))
+        }
+
+        fn metadata(&self) -> Vec<ProviderMetadata> {
+            Vec::new()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_provider() {
+        // Initialize the application with another provider
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(AppData {
                     debug: false,
-                    verbose: false,
-                    asn_database_file: String::from("GeoLite2-ASN.mmdb"),
-                    city_database_file: String::from("GeoLite2-City.mmdb"),
+                    provider: Arc::new(StaticProvider),
+                    max_batch_size: 1000,
                 }))
+                .service(specific_address)
                 .service(healthcheck),
         )
         .await;
 
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/192.0.2.1")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response
+        assert_eq!(result.asn, 64496);
+        assert_eq!(result.summary, String::from("-,-/-; EXAMPLE (64496);"));
+
+        // A provider without any sources is healthy
+        let req = test::TestRequest::get().uri("/healthcheck").to_request();
+        let result: HealthCheckResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(result.is_healthy);
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_overrides() {
+        // Override file for an office network inside a corporate network
+        let override_file = TempFile::new("overrides.yaml");
+        override_file.write(
+            "- network: 10.0.0.0/8\n  organization: Example Corp\n  asn: 64512\n  tags: [corporate]\n\
+             - network: 10.1.2.0/24\n  city: Head Office\n  country: CA\n  tags: [office]\n\
+             - network: 8.8.8.0/24\n  organization: Example DNS\n",
+        );
+        let overrides = Arc::new(OverrideProvider::open(&override_file.0).unwrap());
+        let database = test_database();
+        let args = Args::parse_from(["actix-geo-widget"]);
+        let provider = args
+            .provider(&database, &Some(Arc::clone(&overrides)))
+            .unwrap();
+
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData {
+                    debug: false,
+                    provider,
+                    max_batch_size: 1000,
+                }))
+                .service(specific_address),
+        )
+        .await;
+
+        // Each field is taken from the longest prefix setting it
+        let req = test::TestRequest::get()
+            .uri("/address/10.1.2.3")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(result.city, String::from("Head Office"));
+        assert_eq!(result.country.0, String::from("CA"));
+        assert_eq!(result.asn, 64512);
+        assert_eq!(result.asn_organization, String::from("Example Corp"));
+        assert_eq!(result.tags, vec![String::from("office")]);
+        assert_eq!(result.sources["city"], String::from("overrides"));
+
+        // An entry with only an organization keeps the ASN of the databases
+        let req = test::TestRequest::get()
+            .uri("/address/8.8.8.8")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(result.asn, 15169);
+        assert_eq!(result.asn_organization, String::from("Example DNS"));
+        assert_eq!(result.sources["asn"], String::from("maxmind"));
+        assert_eq!(
+            result.sources["asn_organization"],
+            String::from("overrides")
+        );
+
+        // The names of the databases are in the locale requested, the overrides have no locale
+        let req = test::TestRequest::get()
+            .uri("/address/8.8.8.8?lang=de")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("Content-Language").unwrap(), "de");
+        let result: LookupResult = test::read_body_json(resp).await;
+        assert_eq!(result.locale, String::from("de"));
+        assert!(!result.locale_fallback);
+        assert_eq!(result.country.0, String::from("US"));
+        assert_ne!(result.country.1, String::from("United States"));
+        assert_eq!(result.asn_organization, String::from("Example DNS"));
+
+        // The override file is reloaded with the databases
+        override_file.write("- network: 10.0.0.0/8\n  city: Campus\n");
+        let reloader = DatabaseReloader::new(
+            DatabaseFiles {
+                asn: String::from("GeoLite2-ASN.mmdb"),
+                city: Some(String::from("GeoLite2-City.mmdb")),
+                ..DatabaseFiles::default()
+            },
+            Arc::clone(&database),
+            None,
+        )
+        .with_overrides(Arc::clone(&overrides));
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Reloaded);
+        let req = test::TestRequest::get()
+            .uri("/address/10.1.2.3")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(result.city, String::from("Campus"));
+
+        // A broken override file keeps the overrides in use, the databases are still reloaded
+        let before = database.load().unwrap();
+        override_file.write("- network: not-a-network\n");
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Partial);
+        assert!(!Arc::ptr_eq(&before, &database.load().unwrap()));
+        let req = test::TestRequest::get()
+            .uri("/address/10.1.2.3")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(result.city, String::from("Campus"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_invalid() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/not-an-address")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/problem+json"
+        );
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.status, 400);
+        assert_eq!(result.code, String::from("invalid_address"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_input_forms() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        for (address, canonical) in [
+            ("4.3.2.1:8080", "4.3.2.1"),
+            ("67305985", "4.3.2.1"),
+            ("0x04030201", "4.3.2.1"),
+            ("[2600::1]", "2600::1"),
+            ("[2600:0::1]:443", "2600::1"),
+            ("2600::1%25eth0", "2600::1"),
+        ] {
+            // Send a request to the `specific_address` endpoint
+            let req = test::TestRequest::get()
+                .uri(&format!("/address/{address}"))
+                .to_request();
+
+            // Send the request and parse the response as JSON
+            let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+            // Assert the response
+            assert_eq!(result.address.to_string(), canonical);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_reserved() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/192.168.1.1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert!(resp.status().is_success());
+        let result: LookupResult = test::read_body_json(resp).await;
+        assert_eq!(result.scope, String::from("private"));
+        assert!(result.reserved);
+        assert_eq!(result.asn, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_not_found() {
+        // Initialize the application with a provider without any records
+        let provider = ProviderChain::new(Vec::new());
+        let app_data = web::Data::new(AppData {
+            debug: false,
+            provider: Arc::new(provider),
+            max_batch_size: 1000,
+        });
+        let app = test::init_service(App::new().app_data(app_data).service(specific_address)).await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/8.8.8.8")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("address_not_found"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_database_unavailable() {
+        // Initialize the application without any databases
+        let database = SharedGeoDatabase::unavailable(GeoError::MissingFile {
+            file: String::from("GeoLite2-City.mmdb"),
+            reason: String::from("No such file or directory"),
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData {
+                    debug: false,
+                    provider: Arc::new(MaxMindProvider::new(Arc::new(database), false, false)),
+                    max_batch_size: 1000,
+                }))
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("database_unavailable"));
+    }
+
+    #[actix_web::test]
+    async fn test_batch_address() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(batch_address)).await;
+
+        // Send a request to the `batch_address` endpoint
+        let req = test::TestRequest::post()
+            .uri("/address")
+            .set_json(["4.3.2.1", "not-an-address", "8.8.8.8:53"])
+            .to_request();
+
+        // Send the request and parse the response as JSON
+        let result: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response, results are in the order of the addresses
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0]["address"], json!("4.3.2.1"));
+        assert_eq!(result[0]["asn"], json!(3356));
+        assert_eq!(result[1]["address"], json!("not-an-address"));
+        assert_eq!(result[1]["error"]["code"], json!("invalid_address"));
+        assert_eq!(result[2]["address"], json!("8.8.8.8"));
+        assert_eq!(result[2]["asn"], json!(15169));
+    }
+
+    #[actix_web::test]
+    async fn test_batch_address_too_large() {
+        // Initialize the application with a maximum batch size of 2
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppData {
+                    debug: false,
+                    provider: Arc::new(MaxMindProvider::new(test_database(), false, false)),
+                    max_batch_size: 2,
+                }))
+                .service(batch_address),
+        )
+        .await;
+
+        // Send a request to the `batch_address` endpoint
+        let req = test::TestRequest::post()
+            .uri("/address")
+            .set_json(["4.3.2.1", "8.8.8.8", "1.1.1.1"])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("batch_too_large"));
+    }
+
+    #[actix_web::test]
+    async fn test_batch_address_invalid_body() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(batch_address)).await;
+
+        // Send a request to the `batch_address` endpoint
+        let req = test::TestRequest::post()
+            .uri("/address")
+            .set_payload("4.3.2.1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("invalid_body"));
+    }
+
+    #[actix_web::test]
+    async fn test_stream_address() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(stream_address)).await;
+
+        // Send a request to the `stream_address` endpoint, the last line without a newline
+        let req = test::TestRequest::post()
+            .uri("/address/stream")
+            .set_payload("4.3.2.1\nnot-an-address\n\n{\"ip\": \"8.8.8.8\"}")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response, one JSON result per line in the order of the addresses
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = test::read_body(resp).await;
+        let result: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0]["asn"], json!(3356));
+        assert_eq!(result[1]["error"]["code"], json!("invalid_address"));
+        assert_eq!(result[2]["address"], json!("8.8.8.8"));
+        assert_eq!(result[2]["asn"], json!(15169));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_field() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address_field)
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint for the JSON result
+        let req = test::TestRequest::get()
+            .uri("/address/81.2.69.160")
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        for (field, value) in [
+            ("country", result.country.0.clone()),
+            ("asn", result.asn.to_string()),
+            ("city", result.city.clone()),
+            ("summary", result.summary.clone()),
+        ] {
+            // Send a request to the `specific_address_field` endpoint
+            let req = test::TestRequest::get()
+                .uri(&format!("/address/81.2.69.160/{field}"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            // Assert the response matches the JSON result
+            assert_eq!(
+                resp.headers().get("Content-Type").unwrap(),
+                "text/plain; charset=utf-8"
+            );
+            let body = test::read_body(resp).await;
+            assert_eq!(body, format!("{value}\n"));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_client_address_field() {
+        // Initialize the application, the field endpoint before `specific_address`
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(client_address_field)
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `client_address_field` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address/country")
+            .insert_header(("X-Forwarded-For", "4.3.2.1"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+
+        // Assert the response
+        assert_eq!(body, "US\n");
+    }
+
+    #[actix_web::test]
+    async fn test_client_address_curl() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
+
+        // Send a request to the `client_address` endpoint as curl does
+        let req = test::TestRequest::get()
+            .uri("/address")
+            .insert_header(("User-Agent", "curl/8.5.0"))
+            .insert_header(("Accept", "*/*"))
+            .insert_header(("X-Forwarded-For", "4.3.2.1"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response is the address and summary
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        // The format depends on the User-Agent as well as the Accept header
+        assert_eq!(resp.headers().get("Vary").unwrap(), "User-Agent, Accept");
+        let body = test::read_body(resp).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.starts_with("4.3.2.1 "));
+        assert!(body.ends_with("(3356);\n"));
+
+        // Send a request asking for JSON as curl
+        let req = test::TestRequest::get()
+            .uri("/address")
+            .insert_header(("User-Agent", "curl/8.5.0"))
+            .insert_header(("Accept", "application/json"))
+            .insert_header(("X-Forwarded-For", "4.3.2.1"))
+            .to_request();
+        let result: LookupResult = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response
+        assert_eq!(result.asn, 3356);
+    }
+
+    #[actix_web::test]
+    async fn test_ip() {
+        // Initialize the application
+        let app = test::init_service(App::new().service(ip)).await;
+
+        // Send a request to the `ip` endpoint
+        let req = test::TestRequest::get()
+            .uri("/ip")
+            .insert_header(("X-Forwarded-For", "2600::1"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(body, "2600::1\n");
+    }
+
+    #[actix_web::test]
+    async fn test_client_address_invalid() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(client_address)).await;
+
+        // Send a request to the `client_address` endpoint
+        let req = test::TestRequest::get()
+            .uri("/address")
+            .insert_header(("X-Forwarded-For", "unknown"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("invalid_address"));
+    }
+
+    #[actix_web::test]
+    async fn test_ping() {
+        // Initialize the application
+        let app = test::init_service(App::new().app_data(test_app_data()).service(ping)).await;
+
+        // Send a request to the `ping` endpoint
+        let req = test::TestRequest::get().uri("/ping").to_request();
+
+        // Send the request and parse the response as JSON
+        let result: PongResponse = test::call_and_read_body_json(&app, req).await;
+
+        // Assert the response
+        assert_eq!(result.ping, String::from("pong"));
+    }
+
+    #[actix_web::test]
+    async fn test_healthcheck() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(healthcheck)).await;
+
         // Send a request to the `healthcheck` endpoint
         let req = test::TestRequest::get().uri("/healthcheck").to_request();
 
@@ -704,6 +1802,219 @@ mod tests {
         let result: HealthCheckResponse = test::call_and_read_body_json(&app, req).await;
 
         // Assert the response
-        assert_eq!(result.is_healthy, true);
+        assert!(result.is_healthy);
+    }
+
+    #[actix_web::test]
+    async fn test_healthcheck_text() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(healthcheck)).await;
+
+        // Send a request to the `healthcheck` endpoint
+        let req = test::TestRequest::get()
+            .uri("/healthcheck")
+            .insert_header(("Accept", "text/plain"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        let body = test::read_body(resp).await;
+        assert!(body.starts_with(b"is_healthy: true\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_accept_yaml() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send a request to the `specific_address` endpoint preferring YAML
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .insert_header(("Accept", "application/yaml, application/json;q=0.9"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/yaml"
+        );
+        assert_eq!(resp.headers().get("Vary").unwrap(), "Accept");
+        let body = test::read_body(resp).await;
+        let result: LookupResult = serde_yaml::from_slice(&body).unwrap();
+        assert_eq!(result.asn, 3356);
+
+        // A browser is answered in JSON
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .insert_header((
+                "Accept",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/json"
+        );
+        assert_eq!(resp.headers().get("Vary").unwrap(), "Accept");
+    }
+
+    #[actix_web::test]
+    async fn test_batch_address_format_csv() {
+        // Initialize the application
+        let app =
+            test::init_service(App::new().app_data(test_app_data()).service(batch_address)).await;
+
+        // Send a request to the `batch_address` endpoint, the format overrides the Accept header
+        let req = test::TestRequest::post()
+            .uri("/address?format=csv&fields=address,asn")
+            .insert_header(("Accept", "application/json"))
+            .set_json(["4.3.2.1", "8.8.8.8"])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response, a header row and a row per address
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/csv; charset=utf-8"
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(body, "address,asn\n4.3.2.1,3356\n8.8.8.8,15169\n");
+    }
+
+    #[actix_web::test]
+    async fn test_specific_address_not_acceptable() {
+        // Initialize the application
+        let app = test::init_service(
+            App::new()
+                .app_data(test_app_data())
+                .service(specific_address),
+        )
+        .await;
+
+        // Send requests for an unsupported media type and format
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1")
+            .insert_header(("Accept", "image/png"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let req = test::TestRequest::get()
+            .uri("/address/4.3.2.1?format=pdf")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the response
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let result: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(result.code, String::from("invalid_format"));
+    }
+
+    #[actix_web::test]
+    async fn test_reload_swaps_database() {
+        let database = test_database();
+        let before = database.load().unwrap();
+        let reloader = DatabaseReloader::new(
+            DatabaseFiles {
+                asn: String::from("GeoLite2-ASN.mmdb"),
+                city: Some(String::from("GeoLite2-City.mmdb")),
+                ..DatabaseFiles::default()
+            },
+            Arc::clone(&database),
+            None,
+        );
+
+        // Unchanged files are not reloaded unless forced
+        assert_eq!(reloader.reload(false), reload::ReloadOutcome::Unchanged);
+        assert!(Arc::ptr_eq(&before, &database.load().unwrap()));
+
+        // A forced reload (SIGHUP) swaps in a new handle
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Reloaded);
+        assert!(!Arc::ptr_eq(&before, &database.load().unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn test_reload_dataset_config_file() {
+        let database = test_database();
+        let dataset_config_file = TempFile::new("datasets.json");
+        dataset_config_file.write("{}");
+        let registry = prometheus::Registry::new();
+        let metrics = ReloadMetrics::register("test", &registry).unwrap();
+        let reloader = DatabaseReloader::new(
+            DatabaseFiles {
+                asn: String::from("GeoLite2-ASN.mmdb"),
+                city: Some(String::from("GeoLite2-City.mmdb")),
+                ..DatabaseFiles::default()
+            },
+            Arc::clone(&database),
+            Some(metrics),
+        )
+        .with_dataset_config_file(dataset_config_file.0.clone());
+        assert_eq!(reloader.reload(false), reload::ReloadOutcome::Unchanged);
+
+        // Return the database types with a build epoch gauge
+        let database_types = || -> Vec<String> {
+            let families = registry.gather();
+            let family = families
+                .iter()
+                .find(|family| family.name() == "test_database_build_epoch")
+                .unwrap();
+            family
+                .get_metric()
+                .iter()
+                .map(|metric| metric.get_label()[0].value().to_string())
+                .collect()
+        };
+        assert_eq!(database_types(), vec!["GeoLite2-ASN", "GeoLite2-City"]);
+
+        // A dataset added to the configuration file is opened on reload
+        dataset_config_file.write(
+            r#"{"example": {"file": "GeoLite2-Country.mmdb", "fields": {"country": "country.iso_code"}}}"#,
+        );
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Reloaded);
+        assert!(database.load().unwrap().datasets.contains_key("example"));
+        assert!(database_types().contains(&String::from("GeoLite2-Country")));
+
+        // The datasets in use are kept when the configuration file is invalid,
+        // the databases are still reloaded
+        dataset_config_file.write("not json");
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Partial);
+        assert!(database.load().unwrap().datasets.contains_key("example"));
+
+        // The gauge of a dataset removed from the configuration file is removed
+        dataset_config_file.write("{}");
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Reloaded);
+        assert_eq!(database_types(), vec!["GeoLite2-ASN", "GeoLite2-City"]);
+    }
+
+    #[actix_web::test]
+    async fn test_reload_keeps_database_on_failure() {
+        let database = test_database();
+        let before = database.load().unwrap();
+        let reloader = DatabaseReloader::new(
+            DatabaseFiles {
+                asn: String::from("GeoLite2-ASN.mmdb"),
+                city: Some(String::from("does-not-exist.mmdb")),
+                ..DatabaseFiles::default()
+            },
+            Arc::clone(&database),
+            None,
+        );
+
+        // The current databases are kept when the new files are invalid
+        assert_eq!(reloader.reload(true), reload::ReloadOutcome::Failed);
+        assert!(Arc::ptr_eq(&before, &database.load().unwrap()));
     }
 }
@@ -0,0 +1,348 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+// Types for IPv4 and IPv6 network addresses
+// https://docs.rs/ipnetwork/latest/ipnetwork/
+use ipnetwork::IpNetwork;
+
+// https://docs.rs/serde/latest/serde/
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    effective_address, GeoError, GeoProvider, LookupAsnResult, LookupCityResult, LookupResult,
+    ProviderMetadata,
+};
+
+/// OverrideEntry structure
+///
+/// Information for a network which takes precedence over the databases,
+/// e.g. office networks and VPN pools. Fields left out are taken from the
+/// entries of the networks containing it, or else looked up as usual.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct OverrideEntry {
+    // CIDR, e.g. "10.0.0.0/8"
+    pub network: String,
+    pub city: Option<String>,
+    // ISO 3166-1 code, e.g. "CA", and optionally the name of the country
+    pub country: Option<String>,
+    pub country_name: Option<String>,
+    pub asn: Option<u32>,
+    pub organization: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Row of a CSV override file, tags are separated by ";"
+#[derive(Debug, Deserialize)]
+struct OverrideRow {
+    network: String,
+    city: Option<String>,
+    country: Option<String>,
+    country_name: Option<String>,
+    asn: Option<u32>,
+    organization: Option<String>,
+    tags: Option<String>,
+}
+
+impl From<OverrideRow> for OverrideEntry {
+    fn from(row: OverrideRow) -> Self {
+        OverrideEntry {
+            network: row.network,
+            city: row.city,
+            country: row.country,
+            country_name: row.country_name,
+            asn: row.asn,
+            organization: row.organization,
+            tags: row
+                .tags
+                .unwrap_or_default()
+                .split(';')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// OverrideTable structure
+///
+/// Override entries ordered from the longest to the shortest prefix, so the
+/// first entry containing an address is its longest prefix match.
+#[derive(Clone, Debug, Default)]
+pub struct OverrideTable {
+    entries: Vec<(IpNetwork, OverrideEntry)>,
+}
+
+impl OverrideTable {
+    /// Return an OverrideTable from a list of entries
+    pub fn new(entries: Vec<OverrideEntry>) -> Result<OverrideTable, String> {
+        let mut networks = Vec::new();
+        for entry in entries {
+            let network = entry
+                .network
+                .parse::<IpNetwork>()
+                .map_err(|error| format!("{:?}: {error}", entry.network))?;
+            networks.push((network, entry));
+        }
+        networks.sort_by_key(|(network, _)| std::cmp::Reverse(network.prefix()));
+        Ok(OverrideTable { entries: networks })
+    }
+
+    /// Return an OverrideTable from a YAML, JSON or CSV file (by file extension)
+    ///
+    /// YAML and JSON files are a list of entries, CSV files have a header row of
+    /// `network,city,country,country_name,asn,organization,tags`.
+    pub fn load(override_file: &str) -> Result<OverrideTable, GeoError> {
+        let invalid_config = |reason: String| GeoError::InvalidConfig {
+            file: override_file.to_string(),
+            reason,
+        };
+        let contents =
+            fs::read_to_string(override_file).map_err(|error| invalid_config(error.to_string()))?;
+
+        let extension = Path::new(override_file)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let entries: Vec<OverrideEntry> = match extension.as_str() {
+            // https://docs.rs/serde_yaml/latest/serde_yaml/
+            "yaml" | "yml" => serde_yaml::from_str(&contents)
+                .map_err(|error| invalid_config(error.to_string()))?,
+            "json" => serde_json::from_str(&contents)
+                .map_err(|error| invalid_config(error.to_string()))?,
+            // https://docs.rs/csv/latest/csv/
+            "csv" => csv::Reader::from_reader(contents.as_bytes())
+                .deserialize::<OverrideRow>()
+                .map(|row| row.map(OverrideEntry::from))
+                .collect::<Result<_, _>>()
+                .map_err(|error| invalid_config(error.to_string()))?,
+            _ => {
+                return Err(invalid_config(String::from(
+                    "expected a .yaml, .yml, .json or .csv file",
+                )))
+            }
+        };
+        OverrideTable::new(entries).map_err(invalid_config)
+    }
+
+    /// Return the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return true when there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Return the entry with the longest prefix containing an address
+    pub fn lookup(&self, addr: IpAddr) -> Option<(IpNetwork, &OverrideEntry)> {
+        self.matches(addr).next()
+    }
+
+    /// Return the entries containing an address, from the longest to the shortest prefix
+    pub fn matches(&self, addr: IpAddr) -> impl Iterator<Item = (IpNetwork, &OverrideEntry)> {
+        self.entries
+            .iter()
+            .filter(move |(network, _)| network.contains(addr))
+            .map(|(network, entry)| (*network, entry))
+    }
+}
+
+/// OverrideProvider structure
+///
+/// Provider answering from an override file, placed before the databases in a
+/// ProviderChain. The table is replaced with `store()` while the server is running.
+#[derive(Debug)]
+pub struct OverrideProvider {
+    override_file: String,
+    table: RwLock<Arc<OverrideTable>>,
+}
+
+impl OverrideProvider {
+    /// Return an OverrideProvider with the entries of an override file
+    pub fn open(override_file: &str) -> Result<OverrideProvider, GeoError> {
+        let table = OverrideTable::load(override_file)?;
+        Ok(OverrideProvider {
+            override_file: override_file.to_string(),
+            table: RwLock::new(Arc::new(table)),
+        })
+    }
+
+    /// Return the override file
+    pub fn file(&self) -> &str {
+        &self.override_file
+    }
+
+    /// Return a snapshot of the current OverrideTable
+    pub fn table(&self) -> Arc<OverrideTable> {
+        let table = self.table.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&table)
+    }
+
+    /// Atomically replace the current OverrideTable
+    pub fn store(&self, table: OverrideTable) {
+        let mut current = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        *current = Arc::new(table);
+    }
+}
+
+impl GeoProvider for OverrideProvider {
+    fn name(&self) -> &str {
+        "overrides"
+    }
+
+    fn lookup(&self, addr: IpAddr, _locales: &[String]) -> Result<LookupResult, GeoError> {
+        let table = self.table();
+        let matches: Vec<(IpNetwork, &OverrideEntry)> =
+            table.matches(effective_address(addr)).collect();
+        if matches.is_empty() {
+            return Err(GeoError::AddressNotFound(addr));
+        }
+
+        // Each field is taken from the longest prefix setting it, fields left
+        // out of every entry use the default values
+        let mut asn = LookupAsnResult::default();
+        let asn_entry = matches
+            .iter()
+            .find_map(|(network, entry)| entry.asn.map(|asn| (network, asn)));
+        if let Some((network, asn_number)) = asn_entry {
+            asn.asn = asn_number;
+            asn.network = network.to_string();
+            asn.prefix_len = Some(network.prefix());
+        }
+        if let Some(organization) = matches
+            .iter()
+            .find_map(|(_, entry)| entry.organization.as_ref())
+        {
+            asn.asn_organization = organization.clone();
+        }
+        let mut city = LookupCityResult::default();
+        if let Some(city_name) = matches.iter().find_map(|(_, entry)| entry.city.as_ref()) {
+            city.city = city_name.clone();
+        }
+        // The country name comes from the same entry as the country
+        if let Some((country, entry)) = matches
+            .iter()
+            .find_map(|(_, entry)| entry.country.as_ref().map(|country| (country, entry)))
+        {
+            let country_name = entry.country_name.as_deref().unwrap_or("-");
+            city.country = (country.clone(), country_name.to_string());
+        }
+        if let Some((network, _)) = matches
+            .iter()
+            .find(|(_, entry)| entry.city.is_some() || entry.country.is_some())
+        {
+            city.network = network.to_string();
+            city.prefix_len = Some(network.prefix());
+        }
+        let tags = matches
+            .iter()
+            .map(|(_, entry)| &entry.tags)
+            .find(|tags| !tags.is_empty())
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(LookupResult {
+            tags,
+            ..LookupResult::new(addr, asn, city)
+        })
+    }
+
+    fn metadata(&self) -> Vec<ProviderMetadata> {
+        // The override file is maintained locally and never stale
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(network: &str, city: &str) -> OverrideEntry {
+        OverrideEntry {
+            network: network.to_string(),
+            city: Some(city.to_string()),
+            ..OverrideEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_override_table_longest_prefix() {
+        let table = OverrideTable::new(vec![
+            entry("10.0.0.0/8", "Corporate"),
+            entry("10.1.2.0/24", "Head Office"),
+            entry("fd00::/8", "ULA"),
+        ])
+        .unwrap();
+        let city = |addr: &str| {
+            let addr = addr.parse::<IpAddr>().unwrap();
+            table
+                .lookup(addr)
+                .map(|(_, entry)| entry.city.clone().unwrap())
+        };
+        assert_eq!(city("10.1.2.3"), Some(String::from("Head Office")));
+        assert_eq!(city("10.9.9.9"), Some(String::from("Corporate")));
+        assert_eq!(city("fd12::1"), Some(String::from("ULA")));
+        assert_eq!(city("192.0.2.1"), None);
+    }
+
+    #[test]
+    fn test_override_provider_covering_entries() {
+        let provider = OverrideProvider {
+            override_file: String::new(),
+            table: RwLock::new(Arc::new(
+                OverrideTable::new(vec![
+                    OverrideEntry {
+                        network: String::from("10.0.0.0/8"),
+                        asn: Some(64512),
+                        organization: Some(String::from("Example Corp")),
+                        country: Some(String::from("CA")),
+                        country_name: Some(String::from("Canada")),
+                        tags: vec![String::from("corporate")],
+                        ..OverrideEntry::default()
+                    },
+                    OverrideEntry {
+                        network: String::from("10.1.2.0/24"),
+                        city: Some(String::from("Head Office")),
+                        organization: Some(String::from("Example Office")),
+                        ..OverrideEntry::default()
+                    },
+                ])
+                .unwrap(),
+            )),
+        };
+        let addr = "10.1.2.3".parse::<IpAddr>().unwrap();
+        let result = provider.lookup(addr, &[]).unwrap();
+
+        // Fields set by the /24 are taken from it, the others from the /8
+        assert_eq!(result.city, String::from("Head Office"));
+        assert_eq!(result.asn_organization, String::from("Example Office"));
+        assert_eq!(result.asn, 64512);
+        assert_eq!(result.asn_network, String::from("10.0.0.0/8"));
+        assert_eq!(result.country.1, String::from("Canada"));
+        assert_eq!(result.city_network, String::from("10.1.2.0/24"));
+        assert_eq!(result.tags, vec!["corporate"]);
+    }
+
+    #[test]
+    fn test_override_table_invalid_network() {
+        assert!(OverrideTable::new(vec![entry("10.0.0.0/33", "Corporate")]).is_err());
+    }
+
+    #[test]
+    fn test_override_row_tags() {
+        let csv = "network,city,country,country_name,asn,organization,tags\n\
+                   10.8.0.0/16,,CA,Canada,64512,Example Corp,vpn; staff\n";
+        let rows: Vec<OverrideEntry> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<OverrideRow>()
+            .map(|row| row.unwrap().into())
+            .collect();
+        assert_eq!(rows[0].city, None);
+        assert_eq!(rows[0].asn, Some(64512));
+        assert_eq!(rows[0].tags, vec!["vpn", "staff"]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+// A web framework for Rust
+// https://docs.rs/actix-web/latest/actix_web/error/trait.ResponseError.html
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+
+// https://docs.rs/serde/latest/serde/
+use serde::{Deserialize, Serialize};
+
+// IP information lookup
+use actix_geo_widget::GeoError;
+
+/// Problem details response body (RFC 9457 `application/problem+json`)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    // Machine-readable error code, e.g. "address_not_found"
+    pub code: String,
+}
+
+/// ApiError enum
+///
+/// Errors returned by the endpoints, rendered as `application/problem+json`:
+/// - 400 `invalid_address` the input is not an IP address
+/// - 400 `invalid_body` the request body could not be parsed
+/// - 400 `invalid_fields` a field requested is not part of a lookup result
+/// - 400 `invalid_format` the format requested is not supported
+/// - 406 `not_acceptable` none of the media types accepted is supported
+/// - 413 `batch_too_large` a batch has more addresses than allowed
+/// - 413 `line_too_long` a line of a streamed bulk lookup is longer than allowed
+/// - 404 `address_not_found` the address has no record in the databases
+/// - 503 `database_unavailable` the databases are missing, not configured, of the wrong type or corrupt
+/// - 500 `lookup_failed` the configuration could not be read
+/// - 500 `render_failed` the result could not be rendered in the format requested
+#[derive(Debug)]
+pub enum ApiError {
+    InvalidAddress(String),
+    InvalidBody(String),
+    InvalidFields(String),
+    InvalidFormat(String),
+    NotAcceptable(String),
+    RenderFailed(String),
+    BatchTooLarge { size: usize, max: usize },
+    LineTooLong { line: usize, max: usize },
+    Lookup(GeoError),
+}
+
+impl ApiError {
+    /// Return the HTTP status and machine-readable error code
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            ApiError::InvalidAddress(_) => (StatusCode::BAD_REQUEST, "invalid_address"),
+            ApiError::InvalidBody(_) => (StatusCode::BAD_REQUEST, "invalid_body"),
+            ApiError::InvalidFields(_) => (StatusCode::BAD_REQUEST, "invalid_fields"),
+            ApiError::InvalidFormat(_) => (StatusCode::BAD_REQUEST, "invalid_format"),
+            ApiError::NotAcceptable(_) => (StatusCode::NOT_ACCEPTABLE, "not_acceptable"),
+            ApiError::RenderFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "render_failed"),
+            ApiError::BatchTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "batch_too_large"),
+            ApiError::LineTooLong { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "line_too_long"),
+            ApiError::Lookup(GeoError::AddressNotFound(_)) => {
+                (StatusCode::NOT_FOUND, "address_not_found")
+            }
+            ApiError::Lookup(
+                GeoError::MissingFile { .. }
+                | GeoError::WrongDatabaseType { .. }
+                | GeoError::CorruptData(_)
+                | GeoError::NotConfigured(_),
+            ) => (StatusCode::SERVICE_UNAVAILABLE, "database_unavailable"),
+            ApiError::Lookup(GeoError::InvalidConfig { .. }) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "lookup_failed")
+            }
+        }
+    }
+
+    /// Return the machine-readable error code
+    pub fn code(&self) -> &'static str {
+        self.status_and_code().1
+    }
+
+    /// Return the problem details of the error
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.status_code();
+        ProblemDetails {
+            problem_type: String::from("about:blank"),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            code: self.code().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidAddress(address) => write!(
+                f,
+                "Invalid IP address: {address:?}, expected an IPv4 or IPv6 address \
+                 (optionally bracketed, with a zone ID or a port) or an integer IPv4 address"
+            ),
+            ApiError::InvalidBody(reason) => write!(f, "Invalid request body: {reason}"),
+            ApiError::InvalidFields(field) => write!(f, "Unknown field: {field:?}"),
+            ApiError::InvalidFormat(format) => write!(
+                f,
+                "Unknown format: {format:?}, expected one of json, csv, text, yaml, xml, msgpack or cbor"
+            ),
+            ApiError::NotAcceptable(accept) => {
+                write!(f, "None of the media types accepted are supported: {accept:?}")
+            }
+            ApiError::RenderFailed(reason) => write!(f, "Failed to render the result: {reason}"),
+            ApiError::BatchTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Batch of {size} addresses is larger than the maximum of {max}"
+                )
+            }
+            ApiError::LineTooLong { line, max } => {
+                write!(f, "Line {line} is longer than the maximum of {max} bytes")
+            }
+            ApiError::Lookup(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<GeoError> for ApiError {
+    fn from(err: GeoError) -> Self {
+        ApiError::Lookup(err)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status_and_code().0
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header(("Content-Type", "application/problem+json"))
+            .body(serde_json::to_string_pretty(&self.problem()).unwrap())
+    }
+}
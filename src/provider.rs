@@ -0,0 +1,858 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// Timezone-aware date and time
+// https://docs.rs/chrono/latest/chrono/
+use chrono::{DateTime, Utc};
+
+// A reader for the MaxMind DB format
+// https://docs.rs/maxminddb/latest/maxminddb/struct.Metadata.html
+use maxminddb::Metadata;
+
+// https://docs.rs/serde/latest/serde/
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    classify, effective_address, locale_chain, lookup, lookup_enterprise,
+    lookup_without_enterprise, GeoDatabase, GeoError, LookupAsnResult, LookupCityResult,
+    LookupResult, SharedGeoDatabase,
+};
+
+/// `MAXIMUM_STALE_TTL` is the maximum number of seconds a database
+/// should be used for before being replaced with an updated release.
+pub const MAXIMUM_STALE_TTL: i64 = (604800 * 2) + 86400; // 2 weeks + 1 day
+
+/// ProviderMetadata structure
+///
+/// Describes a database (or other source) backing a provider
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProviderMetadata {
+    // e.g. "GeoLite2-City"
+    pub database_type: String,
+    // Unix timestamp the source was built at
+    pub build_epoch: u64,
+    // Seconds after its build date the source is stale, never stale when unset
+    #[serde(default)]
+    pub max_age: Option<i64>,
+}
+
+impl From<&Metadata> for ProviderMetadata {
+    /// The metadata of a MaxMind edition, stale after `MAXIMUM_STALE_TTL`
+    fn from(metadata: &Metadata) -> Self {
+        ProviderMetadata {
+            database_type: metadata.database_type.clone(),
+            build_epoch: metadata.build_epoch,
+            max_age: Some(MAXIMUM_STALE_TTL),
+        }
+    }
+}
+
+/// ProviderHealth structure
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProviderHealth {
+    pub is_healthy: bool,
+    pub reason: String,
+}
+
+/// Return the health of a provider from the build dates of its sources
+///
+/// A source older than its `max_age` is stale and the provider unhealthy,
+/// sources without a `max_age` are not checked.
+pub fn check_metadata(metadata: &[ProviderMetadata]) -> ProviderHealth {
+    for metadata in metadata {
+        let Some(max_age) = metadata.max_age else {
+            continue;
+        };
+
+        // The build_epoch should reflect a recent version of the database to be considered healthy
+        let build_datetime: DateTime<Utc> =
+            DateTime::from_timestamp(metadata.build_epoch as i64, 0).unwrap_or_default();
+        let database_age = (Utc::now() - build_datetime).num_seconds();
+
+        // Check the if the `database_age` has exceeded the `max_age`
+        if database_age >= max_age {
+            return ProviderHealth {
+                is_healthy: false,
+                reason: format!(
+                    "Database is stale ({} build date: {})",
+                    metadata.database_type, build_datetime,
+                ),
+            };
+        }
+    }
+    ProviderHealth {
+        is_healthy: true,
+        reason: String::from("Check of databases passed"),
+    }
+}
+
+/// GeoProvider trait
+///
+/// A source of IP address information. The MaxMind databases are the default
+/// provider (`MaxMindProvider`), other sources (CSV imports, static tables,
+/// test doubles) implement this trait to be used by the server in their place.
+pub trait GeoProvider: Send + Sync {
+    /// Return the name of the provider, e.g. "maxmind"
+    fn name(&self) -> &str;
+
+    /// Return a LookupResult structure for an IP address
+    ///
+    /// Names are returned in the first of the `locales` (most preferred first)
+    /// available, `GeoError::AddressNotFound` when the provider has no record.
+    fn lookup(&self, addr: IpAddr, locales: &[String]) -> Result<LookupResult, GeoError>;
+
+    /// Return a LookupResult structure (or error) for each of the IP addresses, in order
+    ///
+    /// By default each address is looked up in turn, providers override this to
+    /// share the work of a batch (e.g. one database handle).
+    fn lookup_batch(
+        &self,
+        addrs: &[IpAddr],
+        locales: &[String],
+    ) -> Vec<Result<LookupResult, GeoError>> {
+        addrs
+            .iter()
+            .map(|addr| self.lookup(*addr, locales))
+            .collect()
+    }
+
+    /// Return metadata about the sources backing the provider
+    fn metadata(&self) -> Vec<ProviderMetadata>;
+
+    /// Return the locales names are available in, by default none (only the default locale)
+    fn languages(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Return the health of the provider, by default from the age of its sources
+    fn health(&self) -> ProviderHealth {
+        check_metadata(&self.metadata())
+    }
+}
+
+/// The MaxMind databases a MaxMindProvider looks addresses up in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MaxMindEditions {
+    /// All of the configured databases ("maxmind")
+    #[default]
+    All,
+    /// Only the GeoIP2-Enterprise database ("enterprise")
+    Enterprise,
+    /// All of the configured databases but GeoIP2-Enterprise ("maxmind"), the
+    /// GeoLite2 databases and the other commercial GeoIP2 databases
+    WithoutEnterprise,
+}
+
+impl MaxMindEditions {
+    /// Return the name of a provider using the editions
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaxMindEditions::All => "maxmind",
+            MaxMindEditions::Enterprise => "enterprise",
+            MaxMindEditions::WithoutEnterprise => "maxmind",
+        }
+    }
+}
+
+/// MaxMindProvider structure
+///
+/// The default provider, looks up addresses in the shared MaxMind databases
+/// (so reloaded databases are picked up by the next lookup). A provider can be
+/// limited to some of the databases (`with_editions`), e.g. to chain the
+/// GeoIP2-Enterprise database before the other databases.
+pub struct MaxMindProvider {
+    database: Arc<SharedGeoDatabase>,
+    editions: MaxMindEditions,
+    debug: bool,
+    verbose: bool,
+}
+
+impl MaxMindProvider {
+    /// Return a MaxMindProvider using the shared database handle
+    pub fn new(database: Arc<SharedGeoDatabase>, debug: bool, verbose: bool) -> MaxMindProvider {
+        MaxMindProvider {
+            database,
+            editions: MaxMindEditions::All,
+            debug,
+            verbose,
+        }
+    }
+
+    /// Limit the provider to some of the databases, the name of the provider follows the editions
+    pub fn with_editions(mut self, editions: MaxMindEditions) -> MaxMindProvider {
+        self.editions = editions;
+        self
+    }
+
+    fn lookup_database(
+        &self,
+        database: &GeoDatabase,
+        addr: IpAddr,
+        locales: &[String],
+    ) -> Result<LookupResult, GeoError> {
+        match self.editions {
+            MaxMindEditions::All => lookup(database, addr, locales, self.debug, self.verbose),
+            MaxMindEditions::Enterprise => {
+                lookup_enterprise(database, addr, locales, self.debug, self.verbose)
+            }
+            MaxMindEditions::WithoutEnterprise => {
+                lookup_without_enterprise(database, addr, locales, self.debug, self.verbose)
+            }
+        }
+    }
+}
+
+impl GeoProvider for MaxMindProvider {
+    fn name(&self) -> &str {
+        self.editions.name()
+    }
+
+    fn lookup(&self, addr: IpAddr, locales: &[String]) -> Result<LookupResult, GeoError> {
+        let database = self.database.load()?;
+        self.lookup_database(&database, addr, locales)
+    }
+
+    fn lookup_batch(
+        &self,
+        addrs: &[IpAddr],
+        locales: &[String],
+    ) -> Vec<Result<LookupResult, GeoError>> {
+        // The whole batch is looked up in the same databases, even across a reload
+        match self.database.load() {
+            Ok(database) => addrs
+                .iter()
+                .map(|addr| self.lookup_database(&database, *addr, locales))
+                .collect(),
+            Err(err) => vec![Err(err); addrs.len()],
+        }
+    }
+
+    fn metadata(&self) -> Vec<ProviderMetadata> {
+        match self.database.load() {
+            Ok(database) => {
+                // The Enterprise database is only used by the "enterprise" and "maxmind" providers
+                let enterprise = database.enterprise_metadata();
+                let is_enterprise =
+                    |metadata: &Metadata| enterprise.is_some_and(|e| std::ptr::eq(e, metadata));
+                let mut metadata: Vec<ProviderMetadata> = database
+                    .edition_metadata()
+                    .into_iter()
+                    .filter(|metadata| match self.editions {
+                        MaxMindEditions::All => true,
+                        MaxMindEditions::Enterprise => is_enterprise(metadata),
+                        MaxMindEditions::WithoutEnterprise => !is_enterprise(metadata),
+                    })
+                    .map(ProviderMetadata::from)
+                    .collect();
+                // Datasets are only stale when configured with a `max_age`
+                if self.editions != MaxMindEditions::Enterprise {
+                    metadata.extend(database.datasets.values().map(|dataset| ProviderMetadata {
+                        max_age: dataset.max_age,
+                        ..ProviderMetadata::from(&dataset.reader.metadata)
+                    }));
+                }
+                metadata
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn languages(&self) -> Vec<String> {
+        match self.database.load() {
+            Ok(database) => match self.editions {
+                MaxMindEditions::Enterprise => database
+                    .enterprise_metadata()
+                    .map_or_else(Vec::new, |metadata| metadata.languages.clone()),
+                _ => database.languages().to_vec(),
+            },
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn health(&self) -> ProviderHealth {
+        match self.database.load() {
+            Ok(_) => check_metadata(&self.metadata()),
+            Err(err) => ProviderHealth {
+                is_healthy: false,
+                reason: format!("Databases are unavailable ({err})"),
+            },
+        }
+    }
+}
+
+/// Fields of a LookupResult merged by a ProviderChain
+///
+/// Related fields are merged together, e.g. "asn" covers `asn` and `asn_network`,
+/// "city_network" covers `city_network` and `city_prefix_len`.
+pub const CHAIN_FIELDS: &[&str] = &[
+    "asn",
+    "asn_organization",
+    "city",
+    "continent",
+    "country",
+    "city_network",
+    "subdivisions",
+    "location",
+    "postal",
+    "registered_country",
+    "represented_country",
+    "is_anycast",
+    "is_anonymous_proxy",
+    "is_satellite_provider",
+    "isp",
+    "connection_type",
+    "anonymous",
+    "domain",
+    "confidence",
+    "tags",
+];
+
+/// Fields of CHAIN_FIELDS holding names, in the locale of the provider supplying them
+const NAME_FIELDS: &[&str] = &[
+    "city",
+    "continent",
+    "country",
+    "subdivisions",
+    "registered_country",
+    "represented_country",
+];
+
+/// Copy a field from `result` into `merged` when `result` has a value for it
+///
+/// Returns `false` when the field is missing from `result` ("-", 0, empty or `None`).
+fn take_field(merged: &mut LookupResult, result: &LookupResult, field: &str) -> bool {
+    match field {
+        "asn" if result.asn != 0 => {
+            merged.asn = result.asn;
+            merged.asn_network = result.asn_network.clone();
+            merged.asn_prefix_len = result.asn_prefix_len;
+        }
+        "asn_organization" if result.asn_organization != "-" => {
+            merged.asn_organization = result.asn_organization.clone();
+        }
+        "city" if result.city != "-" => merged.city = result.city.clone(),
+        "continent" if result.continent.0 != "-" => merged.continent = result.continent.clone(),
+        "country" if result.country.0 != "-" => merged.country = result.country.clone(),
+        "city_network" if result.city_network != "-" => {
+            merged.city_network = result.city_network.clone();
+            merged.city_prefix_len = result.city_prefix_len;
+        }
+        "subdivisions" if !result.subdivisions.is_empty() => {
+            merged.subdivisions = result.subdivisions.clone();
+        }
+        "location" if result.location.latitude.is_some() => {
+            merged.location = result.location.clone();
+        }
+        "postal" if result.postal != "-" => merged.postal = result.postal.clone(),
+        "registered_country" if result.registered_country.0 != "-" => {
+            merged.registered_country = result.registered_country.clone();
+        }
+        "represented_country" if result.represented_country.0 != "-" => {
+            merged.represented_country = result.represented_country.clone();
+            merged.represented_country_type = result.represented_country_type.clone();
+        }
+        "is_anycast" if result.is_anycast => merged.is_anycast = true,
+        "is_anonymous_proxy" if result.is_anonymous_proxy => merged.is_anonymous_proxy = true,
+        "is_satellite_provider" if result.is_satellite_provider => {
+            merged.is_satellite_provider = true;
+        }
+        "isp" if result.isp.is_some() => merged.isp = result.isp.clone(),
+        "connection_type" if result.connection_type.is_some() => {
+            merged.connection_type = result.connection_type.clone();
+        }
+        "anonymous" if result.anonymous.is_some() => merged.anonymous = result.anonymous.clone(),
+        "domain" if result.domain.is_some() => merged.domain = result.domain.clone(),
+        "confidence" if result.confidence.is_some() => {
+            merged.confidence = result.confidence.clone();
+        }
+        "tags" if !result.tags.is_empty() => merged.tags = result.tags.clone(),
+        _ => return false,
+    }
+    true
+}
+
+/// Return the locales available in any of the providers, by provider name
+fn all_languages(languages: &[(&str, Vec<String>)]) -> Vec<String> {
+    let mut all: Vec<String> = Vec::new();
+    for language in languages.iter().flat_map(|(_, languages)| languages) {
+        if !all.contains(language) {
+            all.push(language.clone());
+        }
+    }
+    all
+}
+
+/// ProviderChain structure
+///
+/// Looks up an address in several providers and merges their results field
+/// by field. Each field is taken from the first provider with a value for it,
+/// in the order configured for that field (`with_precedence`) or else the
+/// order the providers were added. The provider supplying each field is
+/// recorded in `LookupResult::sources`.
+pub struct ProviderChain {
+    providers: Vec<Arc<dyn GeoProvider>>,
+    precedence: BTreeMap<String, Vec<String>>,
+}
+
+impl ProviderChain {
+    /// Return a ProviderChain using the providers in order of precedence
+    pub fn new(providers: Vec<Arc<dyn GeoProvider>>) -> ProviderChain {
+        ProviderChain {
+            providers,
+            precedence: BTreeMap::new(),
+        }
+    }
+
+    /// Set the order (by provider name) a field is taken from the providers
+    ///
+    /// Providers left out of the order are not used for the field.
+    pub fn with_precedence(mut self, field: &str, order: Vec<String>) -> Result<Self, String> {
+        if !CHAIN_FIELDS.contains(&field) {
+            return Err(format!("Unknown field {field:?}"));
+        }
+        for name in &order {
+            if !self
+                .providers
+                .iter()
+                .any(|provider| provider.name() == name)
+            {
+                return Err(format!("Unknown provider {name:?} for field {field:?}"));
+            }
+        }
+        self.precedence.insert(field.to_string(), order);
+        Ok(self)
+    }
+
+    /// Return the locales names are available in by provider name
+    fn provider_languages(&self) -> Vec<(&str, Vec<String>)> {
+        self.providers
+            .iter()
+            .map(|provider| (provider.name(), provider.languages()))
+            .collect()
+    }
+
+    /// Return the merged result of the lookups of an address, by provider name
+    ///
+    /// `languages` are the locales names are available in by provider name (see
+    /// `provider_languages`), a provider without any has names in no particular
+    /// locale (e.g. the override file).
+    fn merge(
+        &self,
+        addr: IpAddr,
+        locales: &[String],
+        lookups: Vec<(&str, Result<LookupResult, GeoError>)>,
+        languages: &[(&str, Vec<String>)],
+    ) -> Result<LookupResult, GeoError> {
+        // The locale without names in a locale, from the locales available in any provider
+        let default_locale = || locale_chain(locales, &all_languages(languages))[0].clone();
+
+        // Collect the results of the providers with a record for the address
+        let mut results: Vec<(&str, LookupResult)> = Vec::new();
+        for (name, lookup) in lookups {
+            match lookup {
+                Ok(result) => results.push((name, result)),
+                Err(GeoError::AddressNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        if results.is_empty() {
+            // Special-purpose addresses are still classified without a record
+            if classify(effective_address(addr)).is_none() {
+                return Err(GeoError::AddressNotFound(addr));
+            }
+            let city = LookupCityResult {
+                locale: default_locale(),
+                ..LookupCityResult::default()
+            };
+            return Ok(LookupResult::new(addr, LookupAsnResult::default(), city));
+        }
+
+        // Start from an empty result
+        let mut merged = LookupResult::new(
+            addr,
+            LookupAsnResult::default(),
+            LookupCityResult::default(),
+        );
+
+        // Merge each field from the first provider with a value for it
+        let default_order: Vec<&str> = results.iter().map(|(name, _)| *name).collect();
+        for field in CHAIN_FIELDS {
+            let order = match self.precedence.get(*field) {
+                Some(order) => order.iter().map(String::as_str).collect(),
+                None => default_order.clone(),
+            };
+            for name in order {
+                let result = results.iter().find(|(result_name, _)| *result_name == name);
+                if let Some((name, result)) = result {
+                    if take_field(&mut merged, result, field) {
+                        merged.sources.insert(field.to_string(), name.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+
+        // The locale is the locale of the first provider with names in a locale
+        // which supplied a name, `locale_fallback` is set when the names
+        // supplied are not all in that locale
+        let localized: Vec<&LookupResult> = NAME_FIELDS
+            .iter()
+            .filter_map(|field| merged.sources.get(*field))
+            .filter(|name| {
+                languages
+                    .iter()
+                    .any(|(provider, languages)| provider == name && !languages.is_empty())
+            })
+            .filter_map(|name| results.iter().find(|(result_name, _)| result_name == name))
+            .map(|(_, result)| result)
+            .collect();
+        match localized.first() {
+            Some(first) => {
+                merged.locale = first.locale.clone();
+                merged.locale_fallback = localized
+                    .iter()
+                    .any(|result| result.locale_fallback || result.locale != first.locale);
+            }
+            None => merged.locale = default_locale(),
+        }
+
+        // Datasets are merged by name
+        for (name, result) in &results {
+            for (dataset, fields) in &result.datasets {
+                if !merged.datasets.contains_key(dataset) {
+                    merged.datasets.insert(dataset.clone(), fields.clone());
+                    merged
+                        .sources
+                        .insert(format!("datasets.{dataset}"), name.to_string());
+                }
+            }
+        }
+
+        merged.update_summary();
+        Ok(merged)
+    }
+}
+
+impl GeoProvider for ProviderChain {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    fn lookup(&self, addr: IpAddr, locales: &[String]) -> Result<LookupResult, GeoError> {
+        let lookups = self
+            .providers
+            .iter()
+            .map(|provider| (provider.name(), provider.lookup(addr, locales)))
+            .collect();
+        self.merge(addr, locales, lookups, &self.provider_languages())
+    }
+
+    fn lookup_batch(
+        &self,
+        addrs: &[IpAddr],
+        locales: &[String],
+    ) -> Vec<Result<LookupResult, GeoError>> {
+        // Each provider looks up the whole batch, then the results are merged by address
+        let mut batches: Vec<(&str, _)> = self
+            .providers
+            .iter()
+            .map(|provider| {
+                let batch = provider.lookup_batch(addrs, locales);
+                (provider.name(), batch.into_iter())
+            })
+            .collect();
+        let languages = self.provider_languages();
+        addrs
+            .iter()
+            .map(|addr| {
+                let lookups = batches
+                    .iter_mut()
+                    .filter_map(|(name, batch)| batch.next().map(|lookup| (*name, lookup)))
+                    .collect();
+                self.merge(*addr, locales, lookups, &languages)
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> Vec<ProviderMetadata> {
+        self.providers
+            .iter()
+            .flat_map(|provider| provider.metadata())
+            .collect()
+    }
+
+    fn languages(&self) -> Vec<String> {
+        all_languages(&self.provider_languages())
+    }
+
+    fn health(&self) -> ProviderHealth {
+        // The chain is only healthy when all of the providers are
+        for provider in &self.providers {
+            let health = provider.health();
+            if !health.is_healthy {
+                return health;
+            }
+        }
+        ProviderHealth {
+            is_healthy: true,
+            reason: String::from("Check of databases passed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Provider returning the same result for every address
+    struct StaticProvider {
+        name: &'static str,
+        asn: LookupAsnResult,
+        city: LookupCityResult,
+    }
+
+    impl GeoProvider for StaticProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn lookup(&self, addr: IpAddr, _locales: &[String]) -> Result<LookupResult, GeoError> {
+            Ok(LookupResult::new(addr, self.asn.clone(), self.city.clone()))
+        }
+
+        fn metadata(&self) -> Vec<ProviderMetadata> {
+            Vec::new()
+        }
+    }
+
+    fn test_chain() -> ProviderChain {
+        let internal = StaticProvider {
+            name: "internal",
+            asn: LookupAsnResult::default(),
+            city: LookupCityResult {
+                city: String::from("Office"),
+                country: (String::from("CA"), String::from("Canada")),
+                ..LookupCityResult::default()
+            },
+        };
+        let geolite = StaticProvider {
+            name: "geolite",
+            asn: LookupAsnResult {
+                asn: 64496,
+                asn_organization: String::from("EXAMPLE"),
+                ..LookupAsnResult::default()
+            },
+            city: LookupCityResult {
+                city: String::from("Seattle"),
+                country: (String::from("US"), String::from("United States")),
+                continent: (String::from("NA"), String::from("North America")),
+                network: String::from("192.0.2.0/24"),
+                prefix_len: Some(24),
+                ..LookupCityResult::default()
+            },
+        };
+        ProviderChain::new(vec![Arc::new(geolite), Arc::new(internal)])
+    }
+
+    #[test]
+    fn test_provider_chain_precedence() {
+        let addr = "192.0.2.1".parse::<IpAddr>().unwrap();
+
+        // Every field from the first provider with a value for it
+        let result = test_chain().lookup(addr, &[]).unwrap();
+        assert_eq!(result.country.0, String::from("US"));
+        assert_eq!(result.sources["country"], String::from("geolite"));
+
+        // The internal provider takes precedence for the country only
+        let chain = test_chain()
+            .with_precedence(
+                "country",
+                vec![String::from("internal"), String::from("geolite")],
+            )
+            .unwrap();
+        let result = chain.lookup(addr, &[]).unwrap();
+        assert_eq!(result.country.0, String::from("CA"));
+        assert_eq!(result.city, String::from("Seattle"));
+        assert_eq!(result.sources["country"], String::from("internal"));
+        assert_eq!(result.sources["continent"], String::from("geolite"));
+        assert_eq!(result.sources["asn"], String::from("geolite"));
+        assert!(!result.sources.contains_key("postal"));
+        // The network is taken on its own, not with the country
+        assert_eq!(result.city_network, String::from("192.0.2.0/24"));
+        assert_eq!(result.sources["city_network"], String::from("geolite"));
+        assert_eq!(
+            result.summary,
+            String::from("Seattle,-/CA; EXAMPLE (64496);")
+        );
+    }
+
+    #[test]
+    fn test_provider_chain_lookup_batch() {
+        let chain = test_chain()
+            .with_precedence("city", vec![String::from("internal")])
+            .unwrap();
+        let addrs: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+
+        // The batch is merged the same as each lookup
+        let batch = chain.lookup_batch(&addrs, &[]);
+        assert_eq!(batch.len(), addrs.len());
+        for (addr, result) in addrs.iter().zip(batch) {
+            let result = result.unwrap();
+            let expected = chain.lookup(*addr, &[]).unwrap();
+            assert_eq!(result.address, *addr);
+            assert_eq!(result.city, expected.city);
+            assert_eq!(result.summary, expected.summary);
+            assert_eq!(result.sources, expected.sources);
+        }
+    }
+
+    #[test]
+    fn test_maxmind_provider_editions() {
+        // The GeoIP2-Enterprise test database (see `make test-data`)
+        let database = GeoDatabase::open_files(&crate::DatabaseFiles {
+            asn: String::from("GeoLite2-ASN.mmdb"),
+            city: Some(String::from("GeoLite2-City.mmdb")),
+            enterprise: Some(String::from("test-data/GeoIP2-Enterprise-Test.mmdb")),
+            ..crate::DatabaseFiles::default()
+        })
+        .unwrap();
+        let database = Arc::new(SharedGeoDatabase::new(database));
+        let provider = |editions| {
+            let provider = MaxMindProvider::new(Arc::clone(&database), false, false);
+            Arc::new(provider.with_editions(editions))
+        };
+        let enterprise = provider(MaxMindEditions::Enterprise);
+        let maxmind = provider(MaxMindEditions::WithoutEnterprise);
+        assert_eq!(enterprise.name(), "enterprise");
+        assert_eq!(maxmind.name(), "maxmind");
+
+        // Each provider only reports its own databases
+        let database_types = |provider: &MaxMindProvider| -> Vec<String> {
+            let metadata = provider.metadata().into_iter();
+            metadata.map(|metadata| metadata.database_type).collect()
+        };
+        assert_eq!(database_types(&enterprise), vec!["GeoIP2-Enterprise"]);
+        assert!(!database_types(&maxmind).contains(&String::from("GeoIP2-Enterprise")));
+
+        // The Enterprise database is chained before the other databases
+        let addr = "74.209.24.0".parse::<IpAddr>().unwrap();
+        let chain = ProviderChain::new(vec![enterprise, Arc::clone(&maxmind) as _]);
+        let result = chain.lookup(addr, &[]).unwrap();
+        assert_eq!(result.city, String::from("Chatham"));
+        assert_eq!(result.sources["city"], String::from("enterprise"));
+        assert_eq!(result.asn, 14671);
+        assert_eq!(result.sources["confidence"], String::from("enterprise"));
+        assert_eq!(result.confidence.unwrap().city, Some(11));
+        let google = "8.8.8.8".parse::<IpAddr>().unwrap();
+        assert!(maxmind.lookup(google, &[]).unwrap().confidence.is_none());
+
+        // The other databases may be given precedence for a field
+        let chain = chain
+            .with_precedence("city", vec![String::from("maxmind")])
+            .unwrap();
+        let result = chain.lookup(addr, &[]).unwrap();
+        assert_ne!(
+            result.sources.get("city"),
+            Some(&String::from("enterprise"))
+        );
+        assert_eq!(result.sources["confidence"], String::from("enterprise"));
+    }
+
+    #[test]
+    fn test_maxmind_provider_unavailable_batch() {
+        let database = SharedGeoDatabase::unavailable(GeoError::CorruptData(String::from("test")));
+        let provider = MaxMindProvider::new(Arc::new(database), false, false);
+        let addrs: Vec<IpAddr> = vec!["8.8.8.8".parse().unwrap(), "1.1.1.1".parse().unwrap()];
+        let batch = provider.lookup_batch(&addrs, &[]);
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|result| result.is_err()));
+    }
+
+    // Provider without a record for any address, with names in German and English
+    struct EmptyProvider;
+
+    impl GeoProvider for EmptyProvider {
+        fn name(&self) -> &str {
+            "empty"
+        }
+
+        fn lookup(&self, addr: IpAddr, _locales: &[String]) -> Result<LookupResult, GeoError> {
+            Err(GeoError::AddressNotFound(addr))
+        }
+
+        fn metadata(&self) -> Vec<ProviderMetadata> {
+            Vec::new()
+        }
+
+        fn languages(&self) -> Vec<String> {
+            vec![String::from("de"), String::from("en")]
+        }
+    }
+
+    #[test]
+    fn test_provider_chain_special_purpose_locale() {
+        // Without any record a special-purpose address is named in an available locale
+        let addr = "192.168.1.1".parse::<IpAddr>().unwrap();
+        let locales = vec![String::from("xx"), String::from("de-AT")];
+        let result = ProviderChain::new(vec![Arc::new(EmptyProvider)])
+            .lookup(addr, &locales)
+            .unwrap();
+        assert_eq!(result.scope, "private");
+        assert!(result.reserved);
+        assert_eq!(result.locale, "de");
+
+        // A chain without any names uses the default locale
+        let result = ProviderChain::new(Vec::new())
+            .lookup(addr, &locales)
+            .unwrap();
+        assert_eq!(result.locale, "en");
+    }
+
+    #[test]
+    fn test_provider_chain_unknown_precedence() {
+        assert!(test_chain()
+            .with_precedence("colour", vec![String::from("geolite")])
+            .is_err());
+        assert!(test_chain()
+            .with_precedence("country", vec![String::from("enterprise")])
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_metadata_stale() {
+        let now = Utc::now().timestamp() as u64;
+        let mut metadata = vec![ProviderMetadata {
+            database_type: String::from("GeoLite2-City"),
+            build_epoch: now,
+            max_age: Some(MAXIMUM_STALE_TTL),
+        }];
+        assert!(check_metadata(&metadata).is_healthy);
+
+        // A source without a max_age is never stale
+        metadata.push(ProviderMetadata {
+            database_type: String::from("DBIP-City-Lite"),
+            build_epoch: 0,
+            max_age: None,
+        });
+        assert!(check_metadata(&metadata).is_healthy);
+
+        // A database built a month ago is stale
+        metadata.push(ProviderMetadata {
+            database_type: String::from("GeoLite2-ASN"),
+            build_epoch: now - (86400 * 30),
+            max_age: Some(MAXIMUM_STALE_TTL),
+        });
+        let health = check_metadata(&metadata);
+        assert!(!health.is_healthy);
+        assert!(health.reason.contains("GeoLite2-ASN"));
+    }
+
+    #[test]
+    fn test_maxmind_provider_unavailable() {
+        let database = SharedGeoDatabase::unavailable(GeoError::CorruptData(String::from("test")));
+        let provider = MaxMindProvider::new(Arc::new(database), false, false);
+        assert!(!provider.health().is_healthy);
+        assert!(provider.metadata().is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+// A web framework for Rust
+// https://docs.rs/actix-web/latest/actix_web/rt/index.html
+use actix_web::{rt, web};
+
+// Prometheus instrumentation for Rust
+// https://docs.rs/prometheus/latest/prometheus/
+// cargo add prometheus
+use prometheus::{IntCounterVec, IntGaugeVec, Opts, Registry};
+
+use tracing::{debug, error, info};
+
+// IP information lookup
+use actix_geo_widget::{
+    load_datasets, DatabaseFiles, GeoDatabase, OverrideProvider, OverrideTable, SharedGeoDatabase,
+};
+
+/// Outcome of a database reload attempt
+///
+/// `Partial` when some of the files were swapped in and others were invalid,
+/// e.g. the databases were reloaded but the override file was kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReloadOutcome {
+    Reloaded,
+    Partial,
+    Unchanged,
+    Failed,
+}
+
+impl ReloadOutcome {
+    /// Return the outcome as a metric label value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReloadOutcome::Reloaded => "reloaded",
+            ReloadOutcome::Partial => "partial",
+            ReloadOutcome::Unchanged => "unchanged",
+            ReloadOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Reload metrics registered with the Prometheus middleware registry
+#[derive(Clone)]
+pub struct ReloadMetrics {
+    reloads_total: IntCounterVec,
+    build_epoch: IntGaugeVec,
+    // Database types with a build_epoch gauge, removed when no longer in use
+    database_types: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl ReloadMetrics {
+    /// Create and register the reload metrics
+    pub fn register(namespace: &str, registry: &Registry) -> prometheus::Result<ReloadMetrics> {
+        let reloads_total = IntCounterVec::new(
+            Opts::new(
+                "database_reloads_total",
+                "Total number of database reload attempts by outcome",
+            )
+            .namespace(namespace),
+            &["outcome"],
+        )?;
+        let build_epoch = IntGaugeVec::new(
+            Opts::new(
+                "database_build_epoch",
+                "Build epoch of the database currently in use",
+            )
+            .namespace(namespace),
+            &["database_type"],
+        )?;
+        registry.register(Box::new(reloads_total.clone()))?;
+        registry.register(Box::new(build_epoch.clone()))?;
+        Ok(ReloadMetrics {
+            reloads_total,
+            build_epoch,
+            database_types: Arc::new(Mutex::new(BTreeSet::new())),
+        })
+    }
+
+    /// Record the build epochs of the database in use
+    ///
+    /// The gauges of database types no longer in use (e.g. a dataset removed
+    /// from the dataset configuration file) are removed.
+    fn observe(&self, database: &GeoDatabase) {
+        let mut database_types = BTreeSet::new();
+        for metadata in database.metadata() {
+            self.build_epoch
+                .with_label_values(&[metadata.database_type.as_str()])
+                .set(metadata.build_epoch as i64);
+            database_types.insert(metadata.database_type.clone());
+        }
+        let mut observed = self
+            .database_types
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for database_type in observed.difference(&database_types) {
+            let _ = self
+                .build_epoch
+                .remove_label_values(&[database_type.as_str()]);
+        }
+        *observed = database_types;
+    }
+}
+
+/// Reloads the databases (and override and dataset configuration files) from
+/// disk and swaps them into the shared handles
+pub struct DatabaseReloader {
+    // The database files in use, the datasets change with the dataset configuration file
+    files: Mutex<DatabaseFiles>,
+    database: Arc<SharedGeoDatabase>,
+    overrides: Option<Arc<OverrideProvider>>,
+    dataset_config_file: Option<String>,
+    // Modification times of the files seen on the last attempt
+    modified: Mutex<Vec<Option<SystemTime>>>,
+    metrics: Option<ReloadMetrics>,
+}
+
+impl DatabaseReloader {
+    /// Return a DatabaseReloader for the database files backing `database`
+    pub fn new(
+        files: DatabaseFiles,
+        database: Arc<SharedGeoDatabase>,
+        metrics: Option<ReloadMetrics>,
+    ) -> DatabaseReloader {
+        if let (Some(metrics), Ok(current)) = (&metrics, database.load()) {
+            metrics.observe(&current);
+        }
+        let reloader = DatabaseReloader {
+            files: Mutex::new(files),
+            database,
+            overrides: None,
+            dataset_config_file: None,
+            modified: Mutex::new(Vec::new()),
+            metrics,
+        };
+        reloader.observe_modified();
+        reloader
+    }
+
+    /// Also reload the override file of an OverrideProvider
+    pub fn with_overrides(mut self, overrides: Arc<OverrideProvider>) -> DatabaseReloader {
+        self.overrides = Some(overrides);
+        self.observe_modified();
+        self
+    }
+
+    /// Also reload the datasets declared in a dataset configuration file
+    pub fn with_dataset_config_file(mut self, dataset_config_file: String) -> DatabaseReloader {
+        self.dataset_config_file = Some(dataset_config_file);
+        self.observe_modified();
+        self
+    }
+
+    /// Remember the modification times of the files currently in use
+    fn observe_modified(&self) {
+        let modified =
+            self.files_modified(&self.files.lock().unwrap_or_else(PoisonError::into_inner));
+        *self.modified.lock().unwrap_or_else(PoisonError::into_inner) = modified;
+    }
+
+    /// Return the modification times of the database files, override file and dataset configuration file
+    fn files_modified(&self, files: &DatabaseFiles) -> Vec<Option<SystemTime>> {
+        let mut modified: Vec<Option<SystemTime>> = files
+            .paths()
+            .iter()
+            .map(|file| file_modified(file))
+            .collect();
+        if let Some(overrides) = &self.overrides {
+            modified.push(file_modified(overrides.file()));
+        }
+        if let Some(dataset_config_file) = &self.dataset_config_file {
+            modified.push(file_modified(dataset_config_file));
+        }
+        modified
+    }
+
+    /// Reload the databases if the files have changed (or always when `force` is set)
+    ///
+    /// The new files are opened and validated before being swapped in, on any
+    /// error the databases currently in use are kept. An invalid override file
+    /// keeps the overrides in use and an invalid dataset configuration file the
+    /// datasets in use, without holding back the other files (`Partial`).
+    pub fn reload(&self, force: bool) -> ReloadOutcome {
+        let outcome = self.try_reload(force);
+        if let Some(metrics) = &self.metrics {
+            if outcome != ReloadOutcome::Unchanged {
+                metrics
+                    .reloads_total
+                    .with_label_values(&[outcome.as_str()])
+                    .inc();
+            }
+        }
+        outcome
+    }
+
+    fn try_reload(&self, force: bool) -> ReloadOutcome {
+        // Compare the modification times against the last attempt
+        let modified =
+            self.files_modified(&self.files.lock().unwrap_or_else(PoisonError::into_inner));
+        {
+            let mut last_modified = self.modified.lock().unwrap_or_else(PoisonError::into_inner);
+            if !force && *last_modified == modified {
+                return ReloadOutcome::Unchanged;
+            }
+            // Remember this attempt so a broken file is only reported once per change
+            *last_modified = modified;
+        }
+
+        // The override file and the databases are swapped in independently,
+        // a broken file only keeps what it would have replaced
+        let overrides_reloaded = self.reload_overrides();
+        let databases_reloaded = self.reload_databases();
+        match (overrides_reloaded, databases_reloaded) {
+            (None | Some(true), ReloadOutcome::Reloaded) => ReloadOutcome::Reloaded,
+            (None | Some(false), ReloadOutcome::Failed) => ReloadOutcome::Failed,
+            _ => ReloadOutcome::Partial,
+        }
+    }
+
+    /// Load and swap in the override file, returning false when it is invalid
+    /// (`None` without an override file)
+    fn reload_overrides(&self) -> Option<bool> {
+        let overrides = self.overrides.as_ref()?;
+        match OverrideTable::load(overrides.file()) {
+            Ok(table) => {
+                info!(entries = table.len(), "Override file reloaded");
+                overrides.store(table);
+                Some(true)
+            }
+            Err(err) => {
+                error!("Override file reload failed, keeping the current overrides: {err}");
+                Some(false)
+            }
+        }
+    }
+
+    /// Open, validate and swap in the databases
+    ///
+    /// `Partial` when the databases were swapped in but the dataset configuration
+    /// file was invalid, `Failed` when a database file is invalid.
+    fn reload_databases(&self) -> ReloadOutcome {
+        let mut files = self
+            .files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        // Re-read the dataset configuration file, keeping the datasets in use when it is invalid
+        let mut dataset_config_failed = false;
+        if let Some(dataset_config_file) = &self.dataset_config_file {
+            match load_datasets(dataset_config_file) {
+                Ok(datasets) => files.datasets = datasets,
+                Err(err) => {
+                    error!(
+                        "Dataset configuration reload failed, keeping the current datasets: {err}"
+                    );
+                    dataset_config_failed = true;
+                }
+            }
+        }
+
+        // Open the new databases
+        let database = match GeoDatabase::open_files(&files) {
+            Ok(database) => database,
+            Err(err) => {
+                error!(
+                    database_files = ?files.paths(),
+                    "Database reload failed, keeping the current databases: {err}"
+                );
+                return ReloadOutcome::Failed;
+            }
+        };
+
+        // Validate the new MaxMind editions are the same type as the ones in use (if any)
+        if let Ok(current) = self.database.load() {
+            for (current_metadata, new_metadata) in current
+                .edition_metadata()
+                .iter()
+                .zip(database.edition_metadata())
+            {
+                if current_metadata.database_type != new_metadata.database_type {
+                    error!(
+                        "Database reload failed, expected {} but found {}, keeping the current databases",
+                        current_metadata.database_type, new_metadata.database_type,
+                    );
+                    return ReloadOutcome::Failed;
+                }
+            }
+        }
+
+        // Swap in the new databases
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(&database);
+        }
+        for metadata in database.metadata() {
+            info!(
+                database_type = %metadata.database_type,
+                build_epoch = metadata.build_epoch,
+                "Database reloaded"
+            );
+        }
+        self.database.store(database);
+        // The datasets (and so the files to watch) may have changed
+        *self.files.lock().unwrap_or_else(PoisonError::into_inner) = files;
+        self.observe_modified();
+        match dataset_config_failed {
+            true => ReloadOutcome::Partial,
+            false => ReloadOutcome::Reloaded,
+        }
+    }
+}
+
+/// Return the modification time of a file
+fn file_modified(file: &str) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Run a reload on the blocking thread pool (the database files are large)
+async fn reload_blocking(reloader: &Arc<DatabaseReloader>, force: bool) {
+    let reloader = Arc::clone(reloader);
+    match web::block(move || reloader.reload(force)).await {
+        Ok(outcome) => debug!(outcome = outcome.as_str(), force, "Database reload check"),
+        Err(err) => error!("Database reload task failed: {err}"),
+    }
+}
+
+/// Poll the database files every `interval` seconds and reload them when changed
+pub async fn reload_on_change(reloader: Arc<DatabaseReloader>, interval: u64) {
+    let mut interval = rt::time::interval(Duration::from_secs(interval));
+    // The first tick completes immediately, the databases were just opened
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        reload_blocking(&reloader, false).await;
+    }
+}
+
+/// Reload the database files whenever a SIGHUP is received
+#[cfg(unix)]
+pub async fn reload_on_sighup(reloader: Arc<DatabaseReloader>) {
+    use rt::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            error!("Unable to listen for SIGHUP: {err}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading databases");
+        reload_blocking(&reloader, true).await;
+    }
+}
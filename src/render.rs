@@ -0,0 +1,422 @@
+// A web framework for Rust
+// https://docs.rs/actix-web/latest/actix_web/http/header/struct.Accept.html
+use actix_web::{
+    http::header::{Accept, Header, USER_AGENT, VARY},
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+
+// https://docs.rs/serde_json/latest/serde_json/
+use serde_json::{Map, Value};
+
+use crate::problem::ApiError;
+
+/// Format enum
+///
+/// The formats a response can be rendered in, chosen with the `format` query
+/// parameter or else the `Accept` header (JSON by default).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Text,
+    Yaml,
+    Xml,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    /// Return the Format of a `format` query parameter, e.g. "yaml"
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "text" | "txt" => Some(Format::Text),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "xml" => Some(Format::Xml),
+            "msgpack" | "messagepack" => Some(Format::MessagePack),
+            "cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Return the Format of a media type, wildcards are JSON
+    pub fn from_media_type(media_type: &str) -> Option<Format> {
+        match media_type.to_lowercase().as_str() {
+            "application/json" | "application/*" | "*/*" => Some(Format::Json),
+            "text/csv" => Some(Format::Csv),
+            "text/plain" | "text/*" => Some(Format::Text),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(Format::Yaml),
+            "application/xml" | "text/xml" => Some(Format::Xml),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Return the Content-Type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Text => "text/plain; charset=utf-8",
+            Format::Yaml => "application/yaml",
+            Format::Xml => "application/xml",
+            Format::MessagePack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+}
+
+/// Media types of web pages, listed in the `Accept` header of browsers
+const HTML_MEDIA_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+
+/// Vary header of the responses rendered in the format requested
+pub const VARY_ACCEPT: &str = "Accept";
+
+/// Vary header of the "what is my IP" responses, command line tools are answered in plain text
+pub const VARY_CLIENT: &str = "User-Agent, Accept";
+
+/// Return the Format requested with the `format` query parameter or the `Accept` header
+///
+/// An unknown `format` is an error, as is an `Accept` header without any
+/// supported (or wildcard) media type. Without either JSON is returned, as it
+/// is to browsers (an `Accept` header listing HTML) which accept JSON or `*/*`,
+/// e.g. "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8".
+pub fn request_format(req: &HttpRequest, format: Option<&str>) -> Result<Format, ApiError> {
+    if let Some(name) = format {
+        return Format::from_name(name).ok_or_else(|| ApiError::InvalidFormat(name.to_string()));
+    }
+
+    // https://docs.rs/actix-web/latest/actix_web/http/header/struct.Accept.html
+    match Accept::parse(req) {
+        Ok(accept) if !accept.is_empty() => {
+            let ranked = accept.ranked();
+            let formats: Vec<Format> = ranked
+                .iter()
+                .filter_map(|mime| Format::from_media_type(mime.essence_str()))
+                .collect();
+            let browser = ranked
+                .iter()
+                .any(|mime| HTML_MEDIA_TYPES.contains(&mime.essence_str()));
+            match formats.first() {
+                _ if browser && formats.contains(&Format::Json) => Ok(Format::Json),
+                Some(format) => Ok(*format),
+                None => Err(ApiError::NotAcceptable(accept.to_string())),
+            }
+        }
+        _ => Ok(Format::Json),
+    }
+}
+
+/// User-Agent prefixes of the command line tools answered in plain text (lowercase)
+const TERMINAL_CLIENTS: &[&str] = &["curl/", "wget/", "httpie/"];
+
+/// Return true when the request is from a command line tool, e.g. curl
+fn is_terminal_client(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(str::to_lowercase)
+        .is_some_and(|user_agent| {
+            TERMINAL_CLIENTS
+                .iter()
+                .any(|client| user_agent.starts_with(client))
+        })
+}
+
+/// Return the Format for a "what is my IP" request
+///
+/// As `request_format`, except command line tools are answered in plain text
+/// unless they ask for a format with `format` or a media type other than `*/*`.
+pub fn client_format(req: &HttpRequest, format: Option<&str>) -> Result<Format, ApiError> {
+    let requested = request_format(req, format)?;
+    let any_media_type = match Accept::parse(req) {
+        Ok(accept) => accept.iter().all(|item| item.item.essence_str() == "*/*"),
+        Err(_) => true,
+    };
+    if format.is_none() && any_media_type && is_terminal_client(req) {
+        return Ok(Format::Text);
+    }
+    Ok(requested)
+}
+
+/// Return a result in JSON format rendered in a format, and its Content-Type
+///
+/// A list is rendered as a list of results (one row or line per result for
+/// CSV and text), `compact` only applies to the JSON format.
+pub fn render(
+    format: Format,
+    value: &Value,
+    compact: bool,
+) -> Result<(&'static str, Vec<u8>), ApiError> {
+    let render_failed = |err: String| ApiError::RenderFailed(err);
+    let body = match format {
+        // https://docs.rs/serde_json/latest/serde_json/
+        Format::Json if compact => serde_json::to_vec(value).unwrap(),
+        Format::Json => serde_json::to_vec_pretty(value).unwrap(),
+        Format::Csv => render_csv(value).map_err(render_failed)?,
+        Format::Text => render_text(value).into_bytes(),
+        // https://docs.rs/serde_yaml/latest/serde_yaml/
+        Format::Yaml => serde_yaml::to_string(value)
+            .map_err(|err| render_failed(err.to_string()))?
+            .into_bytes(),
+        // https://docs.rs/quick-xml/latest/quick_xml/se/index.html
+        Format::Xml => {
+            let xml = match value {
+                Value::Array(items) => {
+                    let items = Value::Object(Map::from_iter([(
+                        String::from("result"),
+                        Value::Array(items.clone()),
+                    )]));
+                    quick_xml::se::to_string_with_root("results", &items)
+                }
+                _ => quick_xml::se::to_string_with_root("result", value),
+            };
+            xml.map_err(|err| render_failed(err.to_string()))?
+                .into_bytes()
+        }
+        // https://docs.rs/rmp-serde/latest/rmp_serde/
+        Format::MessagePack => {
+            rmp_serde::to_vec_named(value).map_err(|err| render_failed(err.to_string()))?
+        }
+        // https://docs.rs/ciborium/latest/ciborium/
+        Format::Cbor => {
+            let mut body = Vec::new();
+            ciborium::into_writer(value, &mut body)
+                .map_err(|err| render_failed(err.to_string()))?;
+            body
+        }
+    };
+    Ok((format.content_type(), body))
+}
+
+/// Return a response builder and body for a result in JSON format rendered in a format (see `render`)
+///
+/// The Content-Type and Vary headers are set, `vary` names the request headers
+/// the format was chosen by.
+pub fn render_response(
+    format: Format,
+    value: &Value,
+    compact: bool,
+    vary: &'static str,
+) -> Result<(HttpResponseBuilder, Vec<u8>), ApiError> {
+    let (content_type, body) = render(format, value, compact)?;
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(("Content-Type", content_type))
+        .insert_header((VARY, vary));
+    Ok((response, body))
+}
+
+/// Return the items of a result in JSON format, a list is a list of items
+fn items(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        _ => vec![value],
+    }
+}
+
+/// Add the fields of a value to a list of (dot separated) field names and values
+///
+/// e.g. {"country": ["US", "United States"]} -> ("country.0", "US"), ("country.1", "United States")
+fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    let name = |key: &str| match prefix.is_empty() {
+        true => key.to_string(),
+        false => format!("{prefix}.{key}"),
+    };
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(&name(key), value, fields);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.iter().enumerate() {
+                flatten(&name(&index.to_string()), value, fields);
+            }
+        }
+        Value::Null => fields.push((prefix.to_string(), String::new())),
+        Value::String(string) => fields.push((prefix.to_string(), string.clone())),
+        _ => fields.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+/// Return a result in CSV format, a header row of the field names and a row per result
+fn render_csv(value: &Value) -> Result<Vec<u8>, String> {
+    let rows: Vec<Vec<(String, String)>> = items(value)
+        .into_iter()
+        .map(|item| {
+            let mut fields = Vec::new();
+            flatten("", item, &mut fields);
+            fields
+        })
+        .collect();
+
+    // The header is every field name, in the order first seen
+    let mut header: Vec<&str> = Vec::new();
+    for (name, _) in rows.iter().flatten() {
+        if !header.contains(&name.as_str()) {
+            header.push(name);
+        }
+    }
+
+    // https://docs.rs/csv/latest/csv/
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(&header)
+        .map_err(|err| err.to_string())?;
+    for row in &rows {
+        let record = header.iter().map(|name| {
+            row.iter()
+                .find(|(field, _)| field == name)
+                .map_or("", |(_, value)| value.as_str())
+        });
+        writer.write_record(record).map_err(|err| err.to_string())?;
+    }
+    writer.into_inner().map_err(|err| err.to_string())
+}
+
+/// Return a result in plain text, a line per result
+///
+/// A lookup result is "<ADDRESS> <SUMMARY>", an error "<ADDRESS> error: <DETAIL>",
+/// anything else (e.g. a health check or selected fields) a "<FIELD>: <VALUE>" line per field.
+fn render_text(value: &Value) -> String {
+    let mut text = String::new();
+    for item in items(value) {
+        let address = item.get("address").and_then(Value::as_str);
+        let summary = item.get("summary").and_then(Value::as_str);
+        let detail = item.pointer("/error/detail").and_then(Value::as_str);
+        match (address, summary, detail) {
+            (Some(address), Some(summary), _) => text.push_str(&format!("{address} {summary}\n")),
+            (Some(address), _, Some(detail)) => {
+                text.push_str(&format!("{address} error: {detail}\n"))
+            }
+            _ => {
+                let mut fields = Vec::new();
+                flatten("", item, &mut fields);
+                for (name, value) in fields {
+                    text.push_str(&format!("{name}: {value}\n"));
+                }
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_result() -> Value {
+        json!({
+            "address": "8.8.8.8",
+            "asn": 15169,
+            "country": ["US", "United States"],
+            "location": {"latitude": 37.751, "time_zone": null},
+            "summary": "-,-/US; GOOGLE (15169);",
+        })
+    }
+
+    #[test]
+    fn test_format_from_media_type() {
+        assert_eq!(Format::from_media_type("*/*"), Some(Format::Json));
+        assert_eq!(Format::from_media_type("Text/CSV"), Some(Format::Csv));
+        assert_eq!(Format::from_media_type("text/html"), None);
+        assert_eq!(Format::from_name("yml"), Some(Format::Yaml));
+        assert_eq!(Format::from_name("pdf"), None);
+    }
+
+    #[test]
+    fn test_request_format() {
+        let format = |accept: &str| {
+            let req = actix_web::test::TestRequest::get()
+                .insert_header(("Accept", accept))
+                .to_http_request();
+            request_format(&req, None)
+        };
+        assert_eq!(format("application/yaml").unwrap(), Format::Yaml);
+        assert_eq!(format("text/csv, */*;q=0.5").unwrap(), Format::Csv);
+        assert_eq!(format("*/*, application/xml;q=0.5").unwrap(), Format::Json);
+        // Browsers are answered in JSON rather than XML
+        let firefox = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        assert_eq!(format(firefox).unwrap(), Format::Json);
+        let chrome = "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,\
+                      image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7";
+        assert_eq!(format(chrome).unwrap(), Format::Json);
+        // Unless they do not accept JSON
+        assert_eq!(format("text/html, application/xml").unwrap(), Format::Xml);
+        assert_eq!(format("image/png").unwrap_err().code(), "not_acceptable");
+    }
+
+    #[test]
+    fn test_client_format() {
+        let format = |user_agent: &str, accept: &str, format: Option<&str>| {
+            let req = actix_web::test::TestRequest::get()
+                .insert_header(("User-Agent", user_agent))
+                .insert_header(("Accept", accept))
+                .to_http_request();
+            client_format(&req, format).unwrap()
+        };
+        assert_eq!(format("curl/8.5.0", "*/*", None), Format::Text);
+        assert_eq!(format("Wget/1.21.4", "*/*", None), Format::Text);
+        assert_eq!(format("HTTPie/3.2.2", "*/*", None), Format::Text);
+        assert_eq!(format("Mozilla/5.0", "*/*", None), Format::Json);
+        // An explicit media type or format is kept
+        assert_eq!(format("curl/8.5.0", "application/json", None), Format::Json);
+        assert_eq!(format("curl/8.5.0", "*/*", Some("json")), Format::Json);
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let (content_type, body) = render(Format::Csv, &test_result(), false).unwrap();
+        assert_eq!(content_type, "text/csv; charset=utf-8");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "address,asn,country.0,country.1,location.latitude,location.time_zone,summary\n\
+             8.8.8.8,15169,US,United States,37.751,,\"-,-/US; GOOGLE (15169);\"\n"
+        );
+    }
+
+    #[test]
+    fn test_render_text() {
+        let error = json!({"address": "nope", "error": {"detail": "Invalid IP address"}});
+        let value = Value::Array(vec![test_result(), error]);
+        let (_, body) = render(Format::Text, &value, false).unwrap();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "8.8.8.8 -,-/US; GOOGLE (15169);\nnope error: Invalid IP address\n"
+        );
+
+        let (_, body) = render(Format::Text, &json!({"is_healthy": true}), false).unwrap();
+        assert_eq!(String::from_utf8(body).unwrap(), "is_healthy: true\n");
+    }
+
+    #[test]
+    fn test_render_binary_formats() {
+        let value = test_result();
+        let (_, body) = render(Format::MessagePack, &value, false).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded, value);
+
+        let (_, body) = render(Format::Cbor, &value, false).unwrap();
+        let decoded: Value = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_render_xml() {
+        let (_, body) = render(Format::Xml, &test_result(), false).unwrap();
+        let xml = String::from_utf8(body).unwrap();
+        assert!(xml.starts_with("<result><address>8.8.8.8</address><asn>15169</asn>"));
+        assert!(xml.contains("<country>US</country><country>United States</country>"));
+
+        let value = Value::Array(vec![test_result(), test_result()]);
+        let (_, body) = render(Format::Xml, &value, false).unwrap();
+        let xml = String::from_utf8(body).unwrap();
+        assert!(xml.starts_with("<results><result><address>8.8.8.8</address>"));
+    }
+}
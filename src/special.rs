@@ -0,0 +1,212 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::OnceLock;
+
+// Types for IPv4 and IPv6 network addresses
+// https://docs.rs/ipnetwork/latest/ipnetwork/
+use ipnetwork::IpNetwork;
+
+/// Scope of an address which is not in a special-purpose range
+pub const GLOBAL_SCOPE: &str = "global";
+
+/// SpecialPurpose structure
+///
+/// An entry of the IANA special-purpose address registries
+/// https://www.iana.org/assignments/iana-ipv4-special-registry/
+/// https://www.iana.org/assignments/iana-ipv6-special-registry/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpecialPurpose {
+    pub network: &'static str,
+    pub name: &'static str,
+    // Short classification returned as the `scope` of a LookupResult, e.g. "private"
+    pub scope: &'static str,
+    pub rfc: &'static str,
+    pub globally_reachable: bool,
+}
+
+const fn special(
+    network: &'static str,
+    name: &'static str,
+    scope: &'static str,
+    rfc: &'static str,
+    globally_reachable: bool,
+) -> SpecialPurpose {
+    SpecialPurpose {
+        network,
+        name,
+        scope,
+        rfc,
+        globally_reachable,
+    }
+}
+
+/// The IANA IPv4 and IPv6 special-purpose ranges (and the multicast ranges)
+#[rustfmt::skip]
+pub const SPECIAL_PURPOSE: &[SpecialPurpose] = &[
+    // IPv4
+    special("0.0.0.0/8", "This network", "this-network", "RFC 791", false),
+    special("0.0.0.0/32", "This host on this network", "unspecified", "RFC 1122", false),
+    special("10.0.0.0/8", "Private-Use", "private", "RFC 1918", false),
+    special("100.64.0.0/10", "Shared Address Space", "cgnat", "RFC 6598", false),
+    special("127.0.0.0/8", "Loopback", "loopback", "RFC 1122", false),
+    special("169.254.0.0/16", "Link Local", "link-local", "RFC 3927", false),
+    special("172.16.0.0/12", "Private-Use", "private", "RFC 1918", false),
+    special("192.0.0.0/24", "IETF Protocol Assignments", "ietf-protocol", "RFC 6890", false),
+    special("192.0.0.0/29", "IPv4 Service Continuity Prefix", "ietf-protocol", "RFC 7335", false),
+    special("192.0.0.8/32", "IPv4 dummy address", "ietf-protocol", "RFC 7600", false),
+    special("192.0.0.9/32", "Port Control Protocol Anycast", "anycast", "RFC 7723", true),
+    special("192.0.0.10/32", "Traversal Using Relays around NAT Anycast", "anycast", "RFC 8155", true),
+    special("192.0.0.170/31", "NAT64/DNS64 Discovery", "ietf-protocol", "RFC 7050", false),
+    special("192.0.2.0/24", "Documentation (TEST-NET-1)", "documentation", "RFC 5737", false),
+    special("192.31.196.0/24", "AS112-v4", "anycast", "RFC 7535", true),
+    special("192.52.193.0/24", "AMT", "anycast", "RFC 7450", true),
+    special("192.88.99.0/24", "Deprecated (6to4 Relay Anycast)", "reserved", "RFC 7526", false),
+    special("192.168.0.0/16", "Private-Use", "private", "RFC 1918", false),
+    special("192.175.48.0/24", "Direct Delegation AS112 Service", "anycast", "RFC 7534", true),
+    special("198.18.0.0/15", "Benchmarking", "benchmarking", "RFC 2544", false),
+    special("198.51.100.0/24", "Documentation (TEST-NET-2)", "documentation", "RFC 5737", false),
+    special("203.0.113.0/24", "Documentation (TEST-NET-3)", "documentation", "RFC 5737", false),
+    special("224.0.0.0/4", "Multicast", "multicast", "RFC 5771", true),
+    special("240.0.0.0/4", "Reserved", "reserved", "RFC 1112", false),
+    special("255.255.255.255/32", "Limited Broadcast", "broadcast", "RFC 919", false),
+    // IPv6
+    special("::/128", "Unspecified Address", "unspecified", "RFC 4291", false),
+    special("::1/128", "Loopback Address", "loopback", "RFC 4291", false),
+    special("::ffff:0:0/96", "IPv4-mapped Address", "ipv4-mapped", "RFC 4291", false),
+    special("64:ff9b::/96", "IPv4-IPv6 Translat.", "nat64", "RFC 6052", true),
+    special("64:ff9b:1::/48", "IPv4-IPv6 Translat.", "nat64", "RFC 8215", false),
+    special("100::/64", "Discard-Only Address Block", "discard-only", "RFC 6666", false),
+    special("2001::/23", "IETF Protocol Assignments", "ietf-protocol", "RFC 2928", false),
+    special("2001::/32", "TEREDO", "teredo", "RFC 4380", false),
+    special("2001:1::1/128", "Port Control Protocol Anycast", "anycast", "RFC 7723", true),
+    special("2001:1::2/128", "Traversal Using Relays around NAT Anycast", "anycast", "RFC 8155", true),
+    special("2001:2::/48", "Benchmarking", "benchmarking", "RFC 5180", false),
+    special("2001:3::/32", "AMT", "anycast", "RFC 7450", true),
+    special("2001:4:112::/48", "AS112-v6", "anycast", "RFC 7535", true),
+    special("2001:10::/28", "Deprecated (previously ORCHID)", "reserved", "RFC 4843", false),
+    special("2001:20::/28", "ORCHIDv2", "orchid", "RFC 7343", true),
+    special("2001:db8::/32", "Documentation", "documentation", "RFC 3849", false),
+    special("2002::/16", "6to4", "6to4", "RFC 3056", false),
+    special("2620:4f:8000::/48", "Direct Delegation AS112 Service", "anycast", "RFC 7534", true),
+    special("3fff::/20", "Documentation", "documentation", "RFC 9637", false),
+    special("5f00::/16", "Segment Routing (SRv6) SIDs", "ietf-protocol", "RFC 9602", false),
+    special("fc00::/7", "Unique-Local", "unique-local", "RFC 4193", false),
+    special("fe80::/10", "Link-Local Unicast", "link-local", "RFC 4291", false),
+    special("ff00::/8", "Multicast", "multicast", "RFC 4291", true),
+];
+
+/// Return the special-purpose ranges parsed, from the longest to the shortest prefix
+fn special_networks() -> &'static [(IpNetwork, SpecialPurpose)] {
+    static NETWORKS: OnceLock<Vec<(IpNetwork, SpecialPurpose)>> = OnceLock::new();
+    NETWORKS.get_or_init(|| {
+        let mut networks: Vec<(IpNetwork, SpecialPurpose)> = SPECIAL_PURPOSE
+            .iter()
+            .map(|special| (special.network.parse().unwrap(), *special))
+            .collect();
+        networks.sort_by_key(|(network, _)| std::cmp::Reverse(network.prefix()));
+        networks
+    })
+}
+
+/// Return the most specific special-purpose range containing an address
+///
+/// e.g. 192.0.0.9 -> "Port Control Protocol Anycast" rather than "IETF Protocol Assignments"
+pub fn classify(addr: IpAddr) -> Option<&'static SpecialPurpose> {
+    special_networks()
+        .iter()
+        .find(|(network, _)| network.contains(addr))
+        .map(|(_, special)| special)
+}
+
+/// Return the IPv4 address embedded in an IPv6 address, and the name of its encoding
+///
+/// - IPv4-mapped `::ffff:a.b.c.d` (RFC 4291)
+/// - NAT64 `64:ff9b::a.b.c.d` (RFC 6052)
+/// - 6to4 `2002:aabb:ccdd::/48` (RFC 3056)
+/// - Teredo `2001::/32`, the client address is in the last 32 bits inverted (RFC 4380)
+pub fn embedded_ipv4(addr: IpAddr) -> Option<(Ipv4Addr, &'static str)> {
+    let addr = match addr {
+        IpAddr::V4(_) => return None,
+        IpAddr::V6(addr) => addr,
+    };
+    let bits = u128::from(addr);
+    let low = Ipv4Addr::from(bits as u32);
+    if let Some(mapped) = addr.to_ipv4_mapped() {
+        Some((mapped, "ipv4-mapped"))
+    } else if bits >> 32 == u128::from(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0)) >> 32 {
+        Some((low, "nat64"))
+    } else if addr.segments()[0] == 0x2002 {
+        Some((Ipv4Addr::from((bits >> 80) as u32), "6to4"))
+    } else if addr.segments()[0] == 0x2001 && addr.segments()[1] == 0 {
+        Some((Ipv4Addr::from(!(bits as u32)), "teredo"))
+    } else {
+        None
+    }
+}
+
+/// Return the address to look up, the embedded IPv4 address when there is one
+pub fn effective_address(addr: IpAddr) -> IpAddr {
+    match embedded_ipv4(addr) {
+        Some((ipv4, _)) => IpAddr::V4(ipv4),
+        None => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let scope = |addr: &str| classify(addr.parse().unwrap()).map(|special| special.scope);
+        assert_eq!(scope("127.0.0.1"), Some("loopback"));
+        assert_eq!(scope("192.168.1.1"), Some("private"));
+        assert_eq!(scope("100.64.0.1"), Some("cgnat"));
+        assert_eq!(scope("169.254.1.1"), Some("link-local"));
+        assert_eq!(scope("198.19.0.1"), Some("benchmarking"));
+        assert_eq!(scope("239.255.255.250"), Some("multicast"));
+        assert_eq!(scope("2001:db8::1"), Some("documentation"));
+        assert_eq!(scope("fd00::1"), Some("unique-local"));
+        assert_eq!(scope("::1"), Some("loopback"));
+        // The most specific range is used
+        assert_eq!(scope("192.0.0.9"), Some("anycast"));
+        assert_eq!(scope("192.0.0.100"), Some("ietf-protocol"));
+        assert_eq!(scope("0.0.0.0"), Some("unspecified"));
+        // Global unicast addresses are not special-purpose
+        assert_eq!(scope("8.8.8.8"), None);
+        assert_eq!(scope("2600::1"), None);
+    }
+
+    #[test]
+    fn test_embedded_ipv4() {
+        let embedded = |addr: &str| {
+            embedded_ipv4(addr.parse().unwrap())
+                .map(|(ipv4, encoding)| (ipv4.to_string(), encoding))
+        };
+        let google = String::from("8.8.8.8");
+        assert_eq!(
+            embedded("::ffff:8.8.8.8"),
+            Some((google.clone(), "ipv4-mapped"))
+        );
+        assert_eq!(
+            embedded("64:ff9b::808:808"),
+            Some((google.clone(), "nat64"))
+        );
+        assert_eq!(embedded("2002:808:808::1"), Some((google.clone(), "6to4")));
+        assert_eq!(
+            embedded("2001:0:4136:e378:8000:63bf:f7f7:f7f7"),
+            Some((google.clone(), "teredo"))
+        );
+        assert_eq!(embedded("2600::1"), None);
+        assert_eq!(embedded("8.8.8.8"), None);
+        assert_eq!(
+            effective_address("2600::1".parse().unwrap()).to_string(),
+            "2600::1"
+        );
+    }
+
+    #[test]
+    fn test_special_purpose_networks() {
+        // Every network in the table parses
+        assert_eq!(special_networks().len(), SPECIAL_PURPOSE.len());
+    }
+}